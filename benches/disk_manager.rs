@@ -0,0 +1,62 @@
+//! Read throughput of `DiskManager` from several threads, with positional
+//! reads and with a lock around each read standing in for a shared file
+//! cursor. Run with `cargo bench`.
+
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Instant,
+};
+
+use dockbase::common::{
+    config::{DOCKBASE_PAGE_SIZE, PageId},
+    temp_db::TempDb,
+};
+use dockbase::storage::disk::disk_manager::DiskManager;
+
+const NUM_THREADS: usize = 8;
+const NUM_PAGES: usize = 256;
+const READS_PER_THREAD: usize = 2000;
+
+// Returns the reads per second of the workload, serialized or not.
+fn run(dm: &Arc<DiskManager>, serialize: bool) -> f64 {
+    let file_latch = Arc::new(Mutex::new(()));
+    let start = Instant::now();
+    let handles: Vec<_> = (0..NUM_THREADS)
+        .map(|t| {
+            let dm = Arc::clone(dm);
+            let file_latch = Arc::clone(&file_latch);
+            thread::spawn(move || {
+                let mut buf = [0u8; DOCKBASE_PAGE_SIZE];
+                for i in 0..READS_PER_THREAD {
+                    let page_id = ((i * 7 + t * 31) % NUM_PAGES) as PageId;
+                    let _guard = serialize.then(|| file_latch.lock().unwrap());
+                    dm.read_page(page_id, &mut buf).unwrap();
+                    assert_eq!(buf[0], page_id as u8);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    (NUM_THREADS * READS_PER_THREAD) as f64 / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    let db = TempDb::new("bench_disk_manager_reads.db");
+    let dm = DiskManager::new(db.get_path().into()).unwrap();
+    for page_id in 0..NUM_PAGES as PageId {
+        dm.write_page(page_id, &[page_id as u8; DOCKBASE_PAGE_SIZE])
+            .unwrap();
+    }
+    let dm = Arc::new(dm);
+
+    let serialized = run(&dm, true);
+    let positional = run(&dm, false);
+    println!(
+        "{NUM_THREADS} threads: {serialized:.0} reads/s serialized, \
+         {positional:.0} reads/s positional ({:.2}x)",
+        positional / serialized
+    );
+}
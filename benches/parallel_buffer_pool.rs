@@ -0,0 +1,56 @@
+//! Fetch throughput of a `ParallelBufferPoolManager` with one instance and
+//! with one instance per thread. Run with `cargo bench`.
+
+use std::{sync::Arc, thread, time::Instant};
+
+use dockbase::buffer::{
+    buffer_pool_manager::BufferPoolOptions,
+    parallel_buffer_pool_manager::ParallelBufferPoolManager, replacer::AccessType,
+};
+use dockbase::common::{config::PageId, temp_db::TempDb};
+use dockbase::storage::disk::{disk_manager::DiskManager, disk_scheduler::DiskScheduler};
+
+const NUM_THREADS: usize = 8;
+const NUM_PAGES: usize = 64;
+const FETCHES_PER_THREAD: usize = 20_000;
+
+// Runs the same cached workload on `num_instances` pools with `NUM_PAGES`
+// frames in total and returns the fetches per second.
+fn run(num_instances: usize) -> f64 {
+    let db = TempDb::new(format!("bench_parallel_pool_{num_instances}.db"));
+    let disk_manager = Arc::new(DiskManager::new(db.get_path().into()).unwrap());
+    let disk_scheduler = Arc::new(DiskScheduler::new(disk_manager));
+    let options = BufferPoolOptions {
+        pool_size: NUM_PAGES / num_instances,
+        ..Default::default()
+    };
+    let bpm = ParallelBufferPoolManager::with_options(disk_scheduler, num_instances, options);
+    for _ in 0..NUM_PAGES {
+        bpm.new_page_guarded().unwrap();
+    }
+
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for t in 0..NUM_THREADS {
+            let bpm = &bpm;
+            scope.spawn(move || {
+                for i in 0..FETCHES_PER_THREAD {
+                    let page_id = ((i * 7 + t * 31) % NUM_PAGES) as PageId;
+                    let guard = bpm.fetch_page_read(page_id, AccessType::Lookup).unwrap();
+                    assert_eq!(guard.get_page_id(), page_id);
+                }
+            });
+        }
+    });
+    (NUM_THREADS * FETCHES_PER_THREAD) as f64 / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    let single = run(1);
+    let sharded = run(NUM_THREADS);
+    println!(
+        "{NUM_THREADS} threads: {single:.0} fetches/s with one instance, \
+         {sharded:.0} fetches/s with {NUM_THREADS} ({:.2}x)",
+        sharded / single
+    );
+}
@@ -3,22 +3,410 @@ use std::{
     sync::Mutex,
 };
 
-use crate::common::config::{FrameId, PageId};
+use crate::buffer::replacer::{AccessType, Replacer, check_frame};
+use crate::common::{
+    config::{FrameId, PageId},
+    exception::Exception,
+};
+
 struct Node {
     frame_id: FrameId,
     is_evictable: bool,
 }
+
+/// Adaptive Replacement Cache. Pages seen once live in `mru`, pages seen
+/// again in `mfu`, and each has a ghost list remembering the pages recently
+/// evicted from it. A hit in a ghost list shows that list was evicted from
+/// too eagerly, and moves `mru_target_size`, the share of frames `mru`
+/// should keep, in its favour. A scan hit leaves a page in `mru`, so one
+/// pass over a table cannot flood `mfu`.
 pub struct ArcReplacer {
     replacer_size: usize,
+    latch: Mutex<ArcState>,
+}
+
+struct ArcState {
     mru_target_size: usize,
     curr_size: usize,
 
+    // Most recent first. The live lists hold the pages in frames, the ghost
+    // lists only the ids of pages evicted from them.
     mru: VecDeque<PageId>,
     mfu: VecDeque<PageId>,
     mru_ghost: VecDeque<PageId>,
     mfu_ghost: VecDeque<PageId>,
 
     page_table: HashMap<PageId, Node>,
+    frame_table: HashMap<FrameId, PageId>,
+}
+
+impl ArcReplacer {
+    /// Creates a replacer for the frames `0..num_frames`.
+    pub fn new(num_frames: usize) -> Self {
+        Self {
+            replacer_size: num_frames,
+            latch: Mutex::new(ArcState {
+                mru_target_size: 0,
+                curr_size: 0,
+                mru: VecDeque::new(),
+                mfu: VecDeque::new(),
+                mru_ghost: VecDeque::new(),
+                mfu_ghost: VecDeque::new(),
+                page_table: HashMap::new(),
+                frame_table: HashMap::new(),
+            }),
+        }
+    }
+}
+
+impl Replacer for ArcReplacer {
+    fn record_access(
+        &self,
+        frame_id: FrameId,
+        page_id: PageId,
+        access_type: AccessType,
+    ) -> Result<(), Exception> {
+        check_frame(frame_id, self.replacer_size)?;
+        let mut state = self.latch.lock()?;
+        if let Some(node) = state.page_table.get(&page_id) {
+            if node.frame_id != frame_id {
+                return Err(Exception::Invalid("Page is tracked in another frame"));
+            }
+            if access_type == AccessType::Scan && state.mru.contains(&page_id) {
+                return Ok(());
+            }
+            // A hit in either live list makes the page frequently used.
+            remove_page(&mut state.mru, page_id);
+            remove_page(&mut state.mfu, page_id);
+            state.mfu.push_front(page_id);
+            return Ok(());
+        }
+        if state.frame_table.contains_key(&frame_id) {
+            return Err(Exception::Invalid("Frame already holds another page"));
+        }
+
+        let capacity = self.replacer_size;
+        if remove_page(&mut state.mru_ghost, page_id) {
+            let step = (state.mfu_ghost.len() / (state.mru_ghost.len() + 1)).max(1);
+            state.mru_target_size = (state.mru_target_size + step).min(capacity);
+            state.mfu.push_front(page_id);
+        } else if remove_page(&mut state.mfu_ghost, page_id) {
+            let step = (state.mru_ghost.len() / (state.mfu_ghost.len() + 1)).max(1);
+            state.mru_target_size = state.mru_target_size.saturating_sub(step);
+            state.mfu.push_front(page_id);
+        } else {
+            // Keep the history bounded: `mru` and its ghosts together never
+            // exceed the frame count, and all four lists twice that.
+            if state.mru.len() + state.mru_ghost.len() >= capacity {
+                state.mru_ghost.pop_back();
+            } else if state.mru.len()
+                + state.mru_ghost.len()
+                + state.mfu.len()
+                + state.mfu_ghost.len()
+                >= 2 * capacity
+            {
+                state.mfu_ghost.pop_back();
+            }
+            state.mru.push_front(page_id);
+        }
+        state.page_table.insert(
+            page_id,
+            Node {
+                frame_id,
+                is_evictable: false,
+            },
+        );
+        state.frame_table.insert(frame_id, page_id);
+        Ok(())
+    }
+
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> Result<(), Exception> {
+        check_frame(frame_id, self.replacer_size)?;
+        let mut state = self.latch.lock()?;
+        let page_id = *state
+            .frame_table
+            .get(&frame_id)
+            .ok_or(Exception::Invalid("Frame is not tracked by the replacer"))?;
+        let node = state.page_table.get_mut(&page_id).unwrap();
+        if node.is_evictable == set_evictable {
+            return Ok(());
+        }
+        node.is_evictable = set_evictable;
+        match set_evictable {
+            true => state.curr_size += 1,
+            false => state.curr_size -= 1,
+        }
+        Ok(())
+    }
+
+    /// Remembers the evicted page in the matching ghost list. `mru` is
+    /// evicted from first while it holds at least its target share, `mfu`
+    /// otherwise.
+    fn evict_if(&self, filter: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        let mut guard = self.latch.lock().ok()?;
+        let (recent, position) = guard.select(filter)?;
+        let state = &mut *guard;
+        let (list, ghost) = match recent {
+            true => (&mut state.mru, &mut state.mru_ghost),
+            false => (&mut state.mfu, &mut state.mfu_ghost),
+        };
+        let page_id = list.remove(position)?;
+        ghost.push_front(page_id);
+
+        let node = state.page_table.remove(&page_id)?;
+        state.frame_table.remove(&node.frame_id);
+        state.curr_size -= 1;
+        Some(node.frame_id)
+    }
+
+    fn victim(&self) -> Option<FrameId> {
+        let state = self.latch.lock().ok()?;
+        let (recent, position) = state.select(&|_| true)?;
+        let list = match recent {
+            true => &state.mru,
+            false => &state.mfu,
+        };
+        Some(state.page_table[&list[position]].frame_id)
+    }
+
+    /// Unlike eviction, leaves no ghost behind.
+    fn remove(&self, frame_id: FrameId) -> Result<(), Exception> {
+        let mut state = self.latch.lock()?;
+        let Some(&page_id) = state.frame_table.get(&frame_id) else {
+            return Ok(());
+        };
+        if !state.page_table[&page_id].is_evictable {
+            return Err(Exception::Invalid("Cannot remove a non-evictable frame"));
+        }
+        remove_page(&mut state.mru, page_id);
+        remove_page(&mut state.mfu, page_id);
+        state.page_table.remove(&page_id);
+        state.frame_table.remove(&frame_id);
+        state.curr_size -= 1;
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        self.latch.lock().map_or(0, |state| state.curr_size)
+    }
+}
+
+impl ArcState {
+    // Finds the page to evict among those `filter` accepts: whether it is
+    // in `mru` and its position there or in `mfu`.
+    fn select(&self, filter: &dyn Fn(FrameId) -> bool) -> Option<(bool, usize)> {
+        let mru_first = self.mru.len() >= self.mru_target_size;
+        let select_from = |recent: bool| {
+            let list = match recent {
+                true => &self.mru,
+                false => &self.mfu,
+            };
+            let position = list.iter().rposition(|page_id| {
+                let node = &self.page_table[page_id];
+                node.is_evictable && filter(node.frame_id)
+            })?;
+            Some((recent, position))
+        };
+        select_from(mru_first).or_else(|| select_from(!mru_first))
+    }
+}
+
+// Removes `page_id` from `list`, returning whether it was there.
+fn remove_page(list: &mut VecDeque<PageId>, page_id: PageId) -> bool {
+    match list.iter().position(|&id| id == page_id) {
+        Some(position) => {
+            list.remove(position);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mru_target_size(replacer: &ArcReplacer) -> usize {
+        replacer.latch.lock().unwrap().mru_target_size
+    }
+
+    // Records an access of `page_id` in `frame_id` and unpins it.
+    fn access(replacer: &ArcReplacer, frame_id: FrameId, page_id: PageId) {
+        replacer
+            .record_access(frame_id, page_id, AccessType::Lookup)
+            .unwrap();
+        replacer.set_evictable(frame_id, true).unwrap();
+    }
+
+    #[test]
+    fn test_evicts_least_recent_first() {
+        let replacer = ArcReplacer::new(4);
+        for frame_id in 0..4 {
+            access(&replacer, frame_id, frame_id + 100);
+        }
+        assert_eq!(replacer.size(), 4);
+        assert_eq!(replacer.evict(), Some(0));
+        assert_eq!(replacer.evict(), Some(1));
+        assert_eq!(replacer.size(), 2);
+    }
+
+    #[test]
+    fn test_repeated_access_protects_page() {
+        let replacer = ArcReplacer::new(3);
+        for frame_id in 0..3 {
+            access(&replacer, frame_id, frame_id);
+        }
+        // Page 0 moves to mfu; mru is evicted from while it is non-empty.
+        access(&replacer, 0, 0);
+        assert_eq!(replacer.evict(), Some(1));
+        assert_eq!(replacer.evict(), Some(2));
+        assert_eq!(replacer.evict(), Some(0));
+        assert_eq!(replacer.evict(), None);
+    }
+
+    #[test]
+    fn test_scan_access_does_not_promote() {
+        let replacer = ArcReplacer::new(3);
+        for frame_id in 0..3 {
+            access(&replacer, frame_id, frame_id);
+        }
+        replacer.record_access(0, 0, AccessType::Scan).unwrap();
+        assert_eq!(replacer.evict(), Some(0));
+    }
+
+    #[test]
+    fn test_non_evictable_frames_are_skipped() {
+        let replacer = ArcReplacer::new(3);
+        replacer.record_access(0, 10, AccessType::Lookup).unwrap();
+        access(&replacer, 1, 11);
+        access(&replacer, 2, 12);
+        assert_eq!(replacer.size(), 2);
+
+        replacer.set_evictable(1, false).unwrap();
+        assert_eq!(replacer.size(), 1);
+        assert_eq!(replacer.evict(), Some(2));
+        assert_eq!(replacer.evict(), None);
+
+        replacer.set_evictable(0, true).unwrap();
+        replacer.set_evictable(0, true).unwrap();
+        assert_eq!(replacer.size(), 1);
+        assert_eq!(replacer.evict(), Some(0));
+        assert_eq!(replacer.size(), 0);
+    }
+
+    #[test]
+    fn test_mru_ghost_hit_grows_target() {
+        let replacer = ArcReplacer::new(2);
+        access(&replacer, 0, 1);
+        access(&replacer, 1, 2);
+        assert_eq!(replacer.evict(), Some(0));
+        assert_eq!(mru_target_size(&replacer), 0);
+
+        // Page 1 comes back from the mru ghost list into mfu.
+        access(&replacer, 0, 1);
+        assert_eq!(mru_target_size(&replacer), 1);
+        let state = replacer.latch.lock().unwrap();
+        assert_eq!(state.mfu, VecDeque::from([1]));
+        assert!(state.mru_ghost.is_empty());
+    }
+
+    #[test]
+    fn test_mfu_ghost_hit_shrinks_target() {
+        let replacer = ArcReplacer::new(2);
+        access(&replacer, 0, 1);
+        access(&replacer, 1, 2);
+        assert_eq!(replacer.evict(), Some(0));
+        access(&replacer, 0, 1);
+        assert_eq!(mru_target_size(&replacer), 1);
+
+        // mru holds its target, so it is evicted from; page 1 then leaves
+        // mfu for the mfu ghost list.
+        assert_eq!(replacer.evict(), Some(1));
+        access(&replacer, 1, 3);
+        replacer.set_evictable(1, false).unwrap();
+        assert_eq!(replacer.evict(), Some(0));
+        assert_eq!(
+            replacer.latch.lock().unwrap().mfu_ghost,
+            VecDeque::from([1])
+        );
+
+        access(&replacer, 0, 1);
+        assert_eq!(mru_target_size(&replacer), 0);
+        assert!(replacer.latch.lock().unwrap().mfu_ghost.is_empty());
+    }
+
+    #[test]
+    fn test_mfu_evicted_while_mru_below_target() {
+        let replacer = ArcReplacer::new(3);
+        access(&replacer, 0, 1);
+        access(&replacer, 1, 2);
+        access(&replacer, 2, 3);
+        assert_eq!(replacer.evict(), Some(0));
+        access(&replacer, 0, 1);
+        assert_eq!(replacer.evict(), Some(1));
+        access(&replacer, 1, 2);
+        assert_eq!(mru_target_size(&replacer), 2);
+
+        // mru holds only page 3, below its target, so mfu gives up its
+        // least recent page first.
+        assert_eq!(replacer.evict(), Some(0));
+        assert_eq!(replacer.evict(), Some(1));
+        assert_eq!(replacer.evict(), Some(2));
+    }
+
+    #[test]
+    fn test_ghost_lists_stay_bounded() {
+        let num_frames = 4;
+        let replacer = ArcReplacer::new(num_frames);
+        for page_id in 0..100 {
+            let frame_id = match replacer.evict() {
+                Some(frame_id) => frame_id,
+                None => page_id,
+            };
+            access(&replacer, frame_id, page_id);
+            let state = replacer.latch.lock().unwrap();
+            assert!(state.mru.len() + state.mru_ghost.len() <= num_frames);
+            let total =
+                state.mru.len() + state.mfu.len() + state.mru_ghost.len() + state.mfu_ghost.len();
+            assert!(total <= 2 * num_frames);
+        }
+    }
+
+    #[test]
+    fn test_remove() {
+        let replacer = ArcReplacer::new(3);
+        access(&replacer, 0, 10);
+        replacer.record_access(1, 11, AccessType::Lookup).unwrap();
+        assert!(matches!(replacer.remove(1), Err(Exception::Invalid(_))));
+        replacer.remove(0).unwrap();
+        replacer.remove(2).unwrap();
+        assert_eq!(replacer.size(), 0);
+        assert_eq!(replacer.evict(), None);
+
+        // A removed page leaves no ghost behind and comes back as new.
+        access(&replacer, 0, 10);
+        let state = replacer.latch.lock().unwrap();
+        assert_eq!(state.mru, VecDeque::from([10, 11]));
+        assert_eq!(state.mru_target_size, 0);
+    }
 
-    latch: Mutex<()>,
+    #[test]
+    fn test_invalid_frames() {
+        let replacer = ArcReplacer::new(2);
+        assert!(matches!(
+            replacer.record_access(2, 0, AccessType::Lookup),
+            Err(Exception::OutOfRange(_))
+        ));
+        assert!(matches!(
+            replacer.set_evictable(-1, true),
+            Err(Exception::OutOfRange(_))
+        ));
+        assert!(matches!(
+            replacer.set_evictable(1, true),
+            Err(Exception::Invalid(_))
+        ));
+        replacer.record_access(0, 5, AccessType::Lookup).unwrap();
+        assert!(replacer.record_access(1, 5, AccessType::Lookup).is_err());
+        assert!(replacer.record_access(0, 6, AccessType::Lookup).is_err());
+    }
 }
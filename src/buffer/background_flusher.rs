@@ -0,0 +1,179 @@
+use std::{
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::buffer::buffer_pool_manager::BufferPoolManager;
+use crate::common::config::LOG_TIMEOUT;
+use crate::log_warn;
+use crate::storage::disk::disk_scheduler::RequestPriority;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BackgroundFlusherOptions {
+    /// Time between two rounds of write-back.
+    pub interval: Duration,
+}
+
+impl Default for BackgroundFlusherOptions {
+    fn default() -> Self {
+        Self {
+            interval: LOG_TIMEOUT,
+        }
+    }
+}
+
+/// Background thread that periodically writes back the dirty pages nobody
+/// has pinned, at background priority, so that the frames the replacer
+/// evicts are usually clean and foreground misses do not wait for a write.
+/// The thread is stopped when the flusher is dropped.
+pub struct BackgroundFlusher {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    num_flushed_pages: Arc<AtomicUsize>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl BackgroundFlusher {
+    pub fn start(pools: Vec<Arc<BufferPoolManager>>) -> Self {
+        Self::with_options(pools, BackgroundFlusherOptions::default())
+    }
+
+    /// Flushes `pools`, e.g. the instances of a
+    /// `ParallelBufferPoolManager`, one after the other every round.
+    pub fn with_options(
+        pools: Vec<Arc<BufferPoolManager>>,
+        options: BackgroundFlusherOptions,
+    ) -> Self {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let num_flushed_pages = Arc::new(AtomicUsize::new(0));
+        let thread_stop = stop.clone();
+        let thread_num_flushed_pages = num_flushed_pages.clone();
+        let handle = thread::spawn(move || {
+            let (stopped, condvar) = &*thread_stop;
+            let Ok(mut stopped) = stopped.lock() else {
+                return;
+            };
+            while !*stopped {
+                stopped = match condvar.wait_timeout(stopped, options.interval) {
+                    Ok((guard, _)) => guard,
+                    Err(_) => return,
+                };
+                for pool in &pools {
+                    match pool.flush_unpinned_pages(RequestPriority::Background) {
+                        Ok(num_pages) => {
+                            thread_num_flushed_pages.fetch_add(num_pages, Ordering::Relaxed);
+                        }
+                        Err(_) => log_warn!("Background write-back of dirty pages failed"),
+                    }
+                }
+            }
+        });
+        Self {
+            stop,
+            num_flushed_pages,
+            handle: Some(handle),
+        }
+    }
+
+    /// Pages written back by the flusher so far.
+    pub fn get_num_flushed_pages(&self) -> usize {
+        self.num_flushed_pages.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for BackgroundFlusher {
+    fn drop(&mut self) {
+        let (stopped, condvar) = &*self.stop;
+        if let Ok(mut stopped) = stopped.lock() {
+            *stopped = true;
+        }
+        condvar.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{buffer_pool_manager::BufferPoolOptions, replacer::AccessType};
+    use crate::common::temp_db::TempDb;
+    use crate::storage::disk::{disk_manager::DiskManager, disk_scheduler::DiskScheduler};
+    use std::time::Instant;
+
+    fn setup(db_name: &str) -> (Arc<BufferPoolManager>, TempDb) {
+        let db = TempDb::new(db_name);
+        let disk_manager = Arc::new(DiskManager::new(db.get_path().to_path_buf()).unwrap());
+        let disk_scheduler = Arc::new(DiskScheduler::new(disk_manager));
+        let options = BufferPoolOptions {
+            pool_size: 8,
+            ..Default::default()
+        };
+        (
+            Arc::new(BufferPoolManager::with_options(disk_scheduler, options)),
+            db,
+        )
+    }
+
+    #[test]
+    fn test_flushes_unpinned_dirty_pages() {
+        let (bpm, _db) = setup("test_flusher.db");
+        let page_ids: Vec<_> = (0..4u8)
+            .map(|i| {
+                let mut guard = bpm.new_page_guarded().unwrap();
+                guard[0] = i;
+                guard.get_page_id()
+            })
+            .collect();
+        let pinned = bpm.new_page_guarded().unwrap();
+
+        let options = BackgroundFlusherOptions {
+            interval: Duration::from_millis(10),
+        };
+        let flusher = BackgroundFlusher::with_options(vec![bpm.clone()], options);
+        let start = Instant::now();
+        while flusher.get_num_flushed_pages() < page_ids.len() {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(5));
+        }
+        drop(flusher);
+        assert_eq!(
+            bpm.flush_unpinned_pages(RequestPriority::Background)
+                .unwrap(),
+            0
+        );
+
+        let disk_manager = bpm.get_disk_scheduler().get_disk_manager();
+        for (i, &page_id) in page_ids.iter().enumerate() {
+            let page = bpm.fetch_page(page_id, AccessType::Lookup).unwrap();
+            assert!(!page.is_dirty());
+            bpm.unpin_page(page_id, false).unwrap();
+            let mut data = [0u8; 1];
+            disk_manager.read_page(page_id, &mut data).unwrap();
+            assert_eq!(data[0], i as u8);
+        }
+        // The pinned page is left alone.
+        let page = bpm
+            .fetch_page(pinned.get_page_id(), AccessType::Lookup)
+            .unwrap();
+        assert!(page.is_dirty());
+        bpm.unpin_page(page.get_page_id(), false).unwrap();
+        drop(pinned);
+    }
+
+    #[test]
+    fn test_stops_when_dropped() {
+        let (bpm, _db) = setup("test_flusher_stop.db");
+        let flusher = BackgroundFlusher::start(vec![bpm.clone()]);
+        let start = Instant::now();
+        drop(flusher);
+        // Dropping does not wait for the next round.
+        assert!(start.elapsed() < LOG_TIMEOUT);
+        drop(bpm.new_page_guarded().unwrap());
+        assert_eq!(Arc::strong_count(&bpm), 1);
+    }
+}
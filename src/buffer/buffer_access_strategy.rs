@@ -0,0 +1,149 @@
+use std::sync::{Mutex, MutexGuard};
+
+use crate::common::{
+    config::{BULK_READ_RING_SIZE, BULK_WRITE_RING_SIZE, FrameId, PageId},
+    exception::Exception,
+};
+
+/// How the pages read or created through a `BufferAccessStrategy` use the
+/// buffer pool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AccessStrategy {
+    /// Pages take any frame the replacer gives up.
+    #[default]
+    Normal,
+    /// A large sequential scan, which recycles `BULK_READ_RING_SIZE`
+    /// frames.
+    BulkRead,
+    /// A bulk load, which recycles `BULK_WRITE_RING_SIZE` frames and writes
+    /// each page back when its frame comes round again.
+    BulkWrite,
+}
+
+/// Keeps one scan or bulk load from flushing the working set out of the
+/// pool. Pages that miss are loaded into a small private ring of frames,
+/// and once the ring is full the next page reuses the frame of the oldest
+/// one, so the pages the replacer tracks for other callers stay resident.
+/// A frame whose page is pinned, or has been evicted meanwhile, is replaced
+/// in the ring by one taken the normal way. Pages that are already cached
+/// are used where they are.
+#[derive(Debug, Default)]
+pub struct BufferAccessStrategy {
+    strategy: AccessStrategy,
+    ring_size: usize,
+    ring: Mutex<Ring>,
+}
+
+// The frames of the ring, each with the page it was last filled with.
+#[derive(Debug, Default)]
+pub(crate) struct Ring {
+    slots: Vec<(FrameId, PageId)>,
+    next: usize,
+}
+
+impl BufferAccessStrategy {
+    pub fn new(strategy: AccessStrategy) -> Self {
+        let ring_size = match strategy {
+            AccessStrategy::Normal => 0,
+            AccessStrategy::BulkRead => BULK_READ_RING_SIZE,
+            AccessStrategy::BulkWrite => BULK_WRITE_RING_SIZE,
+        };
+        Self::with_ring_size(strategy, ring_size)
+    }
+
+    /// Uses a ring of `ring_size` frames, which a buffer pool caps at an
+    /// eighth of its frames. The `Normal` strategy has no ring.
+    pub fn with_ring_size(strategy: AccessStrategy, ring_size: usize) -> Self {
+        let ring_size = match strategy {
+            AccessStrategy::Normal => 0,
+            _ => ring_size.max(1),
+        };
+        Self {
+            strategy,
+            ring_size,
+            ring: Mutex::new(Ring::default()),
+        }
+    }
+
+    pub fn get_strategy(&self) -> AccessStrategy {
+        self.strategy
+    }
+
+    pub fn get_ring_size(&self) -> usize {
+        self.ring_size
+    }
+
+    /// The ring, or `None` for the `Normal` strategy.
+    pub(crate) fn lock_ring(&self) -> Result<Option<MutexGuard<'_, Ring>>, Exception> {
+        if self.ring_size == 0 {
+            return Ok(None);
+        }
+        Ok(Some(self.ring.lock()?))
+    }
+}
+
+impl Ring {
+    /// The frame and page to be replaced next, once `ring_size` frames have
+    /// been filled.
+    pub(crate) fn next_slot(&self, ring_size: usize) -> Option<(FrameId, PageId)> {
+        if self.slots.len() < ring_size {
+            return None;
+        }
+        Some(self.slots[self.next % self.slots.len()])
+    }
+
+    /// Records that `frame_id` now holds `page_id`, either as a new frame
+    /// of the ring or in place of the slot returned by `next_slot`.
+    pub(crate) fn push(&mut self, frame_id: FrameId, page_id: PageId, ring_size: usize) {
+        if self.slots.len() < ring_size {
+            self.slots.push((frame_id, page_id));
+            return;
+        }
+        let len = self.slots.len();
+        self.slots[self.next % len] = (frame_id, page_id);
+        self.next = (self.next + 1) % len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_sizes() {
+        let normal = BufferAccessStrategy::default();
+        assert_eq!(normal.get_strategy(), AccessStrategy::Normal);
+        assert!(normal.lock_ring().unwrap().is_none());
+        assert_eq!(
+            BufferAccessStrategy::new(AccessStrategy::BulkRead).get_ring_size(),
+            BULK_READ_RING_SIZE
+        );
+        assert_eq!(
+            BufferAccessStrategy::with_ring_size(AccessStrategy::BulkWrite, 0).get_ring_size(),
+            1
+        );
+        assert_eq!(
+            BufferAccessStrategy::with_ring_size(AccessStrategy::Normal, 8).get_ring_size(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_ring_cycles_through_its_frames() {
+        let strategy = BufferAccessStrategy::with_ring_size(AccessStrategy::BulkRead, 3);
+        let mut ring = strategy.lock_ring().unwrap().unwrap();
+        for page_id in 0..3 {
+            assert_eq!(ring.next_slot(3), None);
+            ring.push(page_id, page_id, 3);
+        }
+        // Frames come round oldest first.
+        for page_id in 3..9 {
+            let frame_id = page_id % 3;
+            assert_eq!(ring.next_slot(3), Some((frame_id, page_id - 3)));
+            ring.push(frame_id, page_id, 3);
+        }
+        // A frame that could not be reused is replaced by another one.
+        ring.push(7, 9, 3);
+        assert_eq!(ring.next_slot(3), Some((1, 7)));
+    }
+}
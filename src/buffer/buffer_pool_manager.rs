@@ -0,0 +1,1450 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    mem,
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard,
+        atomic::{AtomicI32, Ordering},
+    },
+};
+
+use crate::buffer::{
+    buffer_access_strategy::{BufferAccessStrategy, Ring},
+    page_guard::{ReadPageGuard, WritePageGuard},
+    replacer::{AccessType, Replacer, ReplacerPolicy},
+};
+use crate::common::{
+    config::{BUFFER_POOL_SIZE, DEFAULT_DB_IO_SIZE, FrameId, INVALID_PAGE_ID, PageId},
+    exception::Exception,
+};
+use crate::storage::{
+    disk::{
+        disk_future::{DiskError, DiskFuture},
+        disk_scheduler::{DiskScheduler, RequestPriority, RequestType},
+        page_buffer::PageBuffer,
+    },
+    page::Page,
+};
+
+// Consecutive fetches of the next page in order that make a scan.
+const READ_AHEAD_TRIGGER: usize = 3;
+
+// Pages `write_back` looks at each time it takes the latch.
+const WRITE_BACK_BATCH: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BufferPoolOptions {
+    /// Number of frames.
+    pub pool_size: usize,
+    /// Policy that picks the frame to evict when no frame is free.
+    pub replacer_policy: ReplacerPolicy,
+    /// Pages prefetched ahead of a sequential scan; 0 disables read-ahead.
+    pub read_ahead_window: usize,
+}
+
+impl Default for BufferPoolOptions {
+    fn default() -> Self {
+        Self {
+            pool_size: BUFFER_POOL_SIZE,
+            replacer_policy: ReplacerPolicy::default(),
+            read_ahead_window: DEFAULT_DB_IO_SIZE,
+        }
+    }
+}
+
+/// Caches pages in a fixed number of frames. Pages are handed out pinned
+/// and latched inside guards, and stay in their frames until every guard
+/// is dropped; unpinned pages are evicted by the replacer when a frame is
+/// needed, and written back first if dirty. The `_with_strategy` variants
+/// keep scans and bulk loads to a ring of frames given by a
+/// `BufferAccessStrategy`.
+pub struct BufferPoolManager {
+    pages: Vec<Arc<Page>>,
+    replacer: Box<dyn Replacer>,
+    disk_scheduler: Arc<DiskScheduler>,
+    next_page_id: AtomicI32,
+    // Distance between the page ids this pool allocates.
+    page_id_stride: PageId,
+    read_ahead_window: usize,
+    latch: Mutex<PoolState>,
+    // Signalled whenever pages leave `PoolState::io_pending`.
+    io_done: Condvar,
+}
+
+struct PoolState {
+    page_table: HashMap<PageId, FrameId>,
+    free_list: VecDeque<FrameId>,
+    // Pages whose disk I/O a thread is doing without the latch: a read into
+    // a reserved frame, the write-back of an evicted page, or a delete.
+    // Other threads wait for them in `wait_for_io`. The reserved frames are
+    // in the page table under their new page; the replacer tracks them,
+    // pinned, only while it still holds an evicted page being written.
+    io_pending: HashSet<PageId>,
+    // Reads issued by prefetching that have not been collected yet. Their
+    // frames are in the page table but not tracked by the replacer.
+    prefetches: HashMap<PageId, DiskFuture>,
+    scan: ScanState,
+}
+
+// A frame taken for another page. If the page evicted from it was dirty,
+// its write-back has been scheduled and must complete before the frame is
+// reused.
+struct Victim {
+    frame_id: FrameId,
+    write_back: Option<(PageId, PageWrite)>,
+    // Whether the frame is recycled from a strategy's ring, which drops the
+    // evicted page from the replacer without keeping its history.
+    recycle: bool,
+}
+
+// A write of a copy of a page, scheduled by `write_page`.
+struct PageWrite {
+    future: DiskFuture,
+    // The dirty state of the page when it was copied.
+    dirty_state: u64,
+}
+
+impl PageWrite {
+    // Waits for the write and marks `page` clean if it succeeded and the
+    // page has not changed since it was copied.
+    fn wait(self, page: &Page) -> Result<(), Exception> {
+        self.future.wait()?;
+        page.set_clean_since(self.dirty_state);
+        Ok(())
+    }
+}
+
+// The sequential access pattern seen so far by `fetch_frame`.
+struct ScanState {
+    last_page_id: PageId,
+    run_length: usize,
+    // Last page prefetched ahead of the current run.
+    prefetched_until: PageId,
+}
+
+impl BufferPoolManager {
+    pub fn new(disk_scheduler: Arc<DiskScheduler>) -> Self {
+        Self::with_options(disk_scheduler, BufferPoolOptions::default())
+    }
+
+    pub fn with_options(disk_scheduler: Arc<DiskScheduler>, options: BufferPoolOptions) -> Self {
+        Self::for_instance(disk_scheduler, options, 0, 1)
+    }
+
+    /// Creates instance `instance_index` of `num_instances` pools sharing
+    /// the disk, which allocates the page ids congruent to its index. The
+    /// first one is above every page already on disk.
+    pub(crate) fn for_instance(
+        disk_scheduler: Arc<DiskScheduler>,
+        options: BufferPoolOptions,
+        instance_index: usize,
+        num_instances: usize,
+    ) -> Self {
+        let (index, stride) = (instance_index as PageId, num_instances as PageId);
+        let first_free = disk_scheduler.get_disk_manager().get_max_page_id() + 1;
+        let first_page_id = first_free + (index - first_free).rem_euclid(stride);
+        Self {
+            pages: (0..options.pool_size)
+                .map(|_| Arc::new(Page::new()))
+                .collect(),
+            replacer: options.replacer_policy.create(options.pool_size),
+            disk_scheduler,
+            next_page_id: AtomicI32::new(first_page_id),
+            page_id_stride: stride,
+            read_ahead_window: options.read_ahead_window,
+            latch: Mutex::new(PoolState {
+                page_table: HashMap::new(),
+                free_list: (0..options.pool_size as FrameId).collect(),
+                io_pending: HashSet::new(),
+                prefetches: HashMap::new(),
+                scan: ScanState {
+                    last_page_id: INVALID_PAGE_ID,
+                    run_length: 0,
+                    prefetched_until: INVALID_PAGE_ID,
+                },
+            }),
+            io_done: Condvar::new(),
+        }
+    }
+
+    /// Like `new_page_guarded`, but returns the page pinned and unlatched,
+    /// to be released with `unpin_page`.
+    #[cfg(test)]
+    pub(crate) fn new_page(&self) -> Result<Arc<Page>, Exception> {
+        self.new_page_with_strategy(&BufferAccessStrategy::default())
+    }
+
+    /// Like `fetch_page_read`, but returns the page pinned and unlatched,
+    /// to be released with `unpin_page`.
+    #[cfg(test)]
+    pub(crate) fn fetch_page(
+        &self,
+        page_id: PageId,
+        access_type: AccessType,
+    ) -> Result<Arc<Page>, Exception> {
+        self.fetch_page_with_strategy(page_id, access_type, &BufferAccessStrategy::default())
+    }
+
+    /// Allocates a page id and returns the new, zeroed page latched
+    /// exclusively inside a guard that unpins it when dropped. Fails when
+    /// every frame is pinned.
+    pub fn new_page_guarded(&self) -> Result<WritePageGuard<'_>, Exception> {
+        self.new_page_guarded_with_strategy(&BufferAccessStrategy::default())
+    }
+
+    /// Returns `page_id` latched for reading inside a guard that unpins it
+    /// when dropped, reading it from disk if it is not cached.
+    pub fn fetch_page_read(
+        &self,
+        page_id: PageId,
+        access_type: AccessType,
+    ) -> Result<ReadPageGuard<'_>, Exception> {
+        self.fetch_page_read_with_strategy(page_id, access_type, &BufferAccessStrategy::default())
+    }
+
+    /// Like `fetch_page_read`, but the page is latched exclusively.
+    pub fn fetch_page_write(
+        &self,
+        page_id: PageId,
+        access_type: AccessType,
+    ) -> Result<WritePageGuard<'_>, Exception> {
+        self.fetch_page_write_with_strategy(page_id, access_type, &BufferAccessStrategy::default())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn new_page_with_strategy(
+        &self,
+        strategy: &BufferAccessStrategy,
+    ) -> Result<Arc<Page>, Exception> {
+        self.new_frame(strategy).cloned()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn fetch_page_with_strategy(
+        &self,
+        page_id: PageId,
+        access_type: AccessType,
+        strategy: &BufferAccessStrategy,
+    ) -> Result<Arc<Page>, Exception> {
+        self.fetch_frame(page_id, access_type, strategy).cloned()
+    }
+
+    /// Like `new_page_guarded`, but the page takes a frame of `strategy`'s
+    /// ring.
+    pub fn new_page_guarded_with_strategy(
+        &self,
+        strategy: &BufferAccessStrategy,
+    ) -> Result<WritePageGuard<'_>, Exception> {
+        WritePageGuard::new(self, self.new_frame(strategy)?)
+    }
+
+    /// Like `fetch_page_read`, but a page read from disk takes a frame of
+    /// `strategy`'s ring. Fetches through a ring do not read ahead, which
+    /// would fill frames outside of it.
+    pub fn fetch_page_read_with_strategy(
+        &self,
+        page_id: PageId,
+        access_type: AccessType,
+        strategy: &BufferAccessStrategy,
+    ) -> Result<ReadPageGuard<'_>, Exception> {
+        ReadPageGuard::new(self, self.fetch_frame(page_id, access_type, strategy)?)
+    }
+
+    pub fn fetch_page_write_with_strategy(
+        &self,
+        page_id: PageId,
+        access_type: AccessType,
+        strategy: &BufferAccessStrategy,
+    ) -> Result<WritePageGuard<'_>, Exception> {
+        WritePageGuard::new(self, self.fetch_frame(page_id, access_type, strategy)?)
+    }
+
+    /// Releases one pin of `page_id`. `is_dirty` records that the caller
+    /// modified the page; it never clears an earlier modification.
+    pub(crate) fn unpin_page(&self, page_id: PageId, is_dirty: bool) -> Result<(), Exception> {
+        let state = self.latch.lock()?;
+        let &frame_id = state
+            .page_table
+            .get(&page_id)
+            .ok_or(Exception::Invalid("Page is not in the buffer pool"))?;
+        let page = &self.pages[frame_id as usize];
+        if page.get_pin_count() == 0 {
+            return Err(Exception::Invalid("Page is not pinned"));
+        }
+        if is_dirty {
+            page.set_dirty(true);
+        }
+        if page.unpin() == 0 {
+            self.replacer.set_evictable(frame_id, true)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `page_id` to disk, whether or not it is dirty. Waits for the
+    /// page latch, so a thread holding a guard of the page flushes it
+    /// through the guard instead.
+    pub fn flush_page(&self, page_id: PageId) -> Result<(), Exception> {
+        let page = {
+            let state = self.wait_for_io(self.latch.lock()?, page_id)?;
+            let &frame_id = state
+                .page_table
+                .get(&page_id)
+                .ok_or(Exception::Invalid("Page is not in the buffer pool"))?;
+            let page = &self.pages[frame_id as usize];
+            self.hold(frame_id, page)?;
+            page.clone()
+        };
+        let result = self
+            .write_page(&page, RequestPriority::Foreground)
+            .and_then(|write| write.wait(&page));
+        self.unpin_page(page_id, false)?;
+        result
+    }
+
+    /// Writes every dirty page to disk. The writes are issued together and
+    /// awaited at the end. Waits for the latches of pinned dirty pages, so
+    /// it must not be called by a thread holding a guard.
+    pub fn flush_all_pages(&self) -> Result<(), Exception> {
+        self.write_back(RequestPriority::Foreground, |_| true)
+            .map(|_| ())
+    }
+
+    /// Writes every dirty page nobody has pinned at `priority` and returns
+    /// how many were written. Evicting such a page later does not have to
+    /// wait for a write.
+    pub fn flush_unpinned_pages(&self, priority: RequestPriority) -> Result<usize, Exception> {
+        self.write_back(priority, |page| page.get_pin_count() == 0)
+    }
+
+    /// Starts reading `page_ids` into the pool without waiting for them, so
+    /// that fetching them later does not block on the disk. Cached pages
+    /// are skipped, and prefetching stops at the first page no frame can
+    /// be found for. Returns the number of reads issued.
+    pub fn prefetch_pages(&self, page_ids: &[PageId]) -> Result<usize, Exception> {
+        let mut state = self.latch.lock()?;
+        self.collect_prefetches(&mut state);
+        Ok(self.issue_prefetches(&mut state, page_ids.iter().copied()))
+    }
+
+    /// Drops `page_id` from the pool and deletes it on disk. Fails if the
+    /// page is pinned.
+    pub fn delete_page(&self, page_id: PageId) -> Result<(), Exception> {
+        let state = self.wait_for_io(self.latch.lock()?, page_id)?;
+        let mut state = self.collect_prefetch(state, page_id)?;
+        if let Some(&frame_id) = state.page_table.get(&page_id) {
+            let page = &self.pages[frame_id as usize];
+            if page.get_pin_count() > 0 {
+                return Err(Exception::Invalid("Cannot delete a pinned page"));
+            }
+            self.replacer.remove(frame_id)?;
+            state.page_table.remove(&page_id);
+            page.reset(INVALID_PAGE_ID);
+            state.free_list.push_back(frame_id);
+        }
+        // Keeps the page from being read back before it is gone.
+        state.io_pending.insert(page_id);
+        drop(state);
+        let result = self
+            .disk_scheduler
+            .schedule_async(RequestType::Delete, page_id, PageBuffer::empty())
+            .wait();
+        self.latch.lock()?.io_pending.remove(&page_id);
+        self.io_done.notify_all();
+        result.map(|_| ()).map_err(Exception::from)
+    }
+
+    pub fn get_pool_size(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// The pin count of `page_id`, or `None` if it is not in the pool.
+    pub fn get_pin_count(&self, page_id: PageId) -> Option<usize> {
+        let state = self.latch.lock().ok()?;
+        let &frame_id = state.page_table.get(&page_id)?;
+        Some(self.pages[frame_id as usize].get_pin_count())
+    }
+
+    pub fn get_disk_scheduler(&self) -> &Arc<DiskScheduler> {
+        &self.disk_scheduler
+    }
+
+    fn new_frame(&self, strategy: &BufferAccessStrategy) -> Result<&Arc<Page>, Exception> {
+        let mut state = self.latch.lock()?;
+        let (victim, page_id) = loop {
+            let allocate = || {
+                self.next_page_id
+                    .fetch_add(self.page_id_stride, Ordering::Relaxed)
+            };
+            match self.reserve_frame(&mut state, strategy, allocate)? {
+                Some(reserved) => break reserved,
+                None => state = self.wait_for_frame(state)?,
+            }
+        };
+        // Read-ahead may have tried to read the page before it existed.
+        let state = self.collect_prefetch(state, page_id)?;
+        let frame_id = victim.frame_id;
+        let state = self.fill_frame(state, victim, page_id, false)?;
+        let page = &self.pages[frame_id as usize];
+        page.write()?.fill(0);
+        // The disk manager only knows pages that were written, so a new
+        // page is dirty until it is first flushed.
+        page.set_dirty(true);
+        self.pin(frame_id, page, AccessType::Unknown)?;
+        drop(state);
+        Ok(page)
+    }
+
+    fn fetch_frame(
+        &self,
+        page_id: PageId,
+        access_type: AccessType,
+        strategy: &BufferAccessStrategy,
+    ) -> Result<&Arc<Page>, Exception> {
+        if page_id == INVALID_PAGE_ID {
+            return Err(Exception::Invalid("Invalid page id"));
+        }
+        let mut state = self.latch.lock()?;
+        // Starts over whenever the latch was released meanwhile, since
+        // another thread may have read the page.
+        let frame_id = loop {
+            state = self.wait_for_io(state, page_id)?;
+            self.collect_prefetches(&mut state);
+            // A failed prefetch is retried below, which reports the error.
+            state = self.collect_prefetch(state, page_id)?;
+            if let Some(&frame_id) = state.page_table.get(&page_id) {
+                break frame_id;
+            }
+            match self.reserve_frame(&mut state, strategy, || page_id)? {
+                Some((victim, _)) => {
+                    let frame_id = victim.frame_id;
+                    state = self.fill_frame(state, victim, page_id, true)?;
+                    break frame_id;
+                }
+                None => state = self.wait_for_frame(state)?,
+            }
+        };
+        let page = &self.pages[frame_id as usize];
+        self.pin(frame_id, page, access_type)?;
+        if strategy.get_ring_size() == 0 {
+            self.read_ahead(&mut state, page_id);
+        }
+        Ok(page)
+    }
+
+    // The ring size of `strategy` in this pool, at most an eighth of the
+    // frames.
+    fn ring_size(&self, strategy: &BufferAccessStrategy) -> usize {
+        strategy.get_ring_size().min(self.pages.len() / 8).max(1)
+    }
+
+    // Follows the pages this pool allocates in order: once a run of
+    // `READ_AHEAD_TRIGGER` of them has been fetched, keeps up to
+    // `read_ahead_window` pages past the current one prefetched. The
+    // window is topped up once half of it has been consumed, so the reads
+    // go out in batches.
+    fn read_ahead(&self, state: &mut PoolState, page_id: PageId) {
+        if self.read_ahead_window == 0 {
+            return;
+        }
+        let stride = self.page_id_stride;
+        let scan = &mut state.scan;
+        if page_id == scan.last_page_id + stride {
+            scan.run_length += 1;
+        } else if page_id != scan.last_page_id {
+            scan.run_length = 1;
+            scan.prefetched_until = page_id;
+        }
+        scan.last_page_id = page_id;
+
+        let window = self.read_ahead_window as PageId * stride;
+        if scan.run_length < READ_AHEAD_TRIGGER || scan.prefetched_until - page_id > window / 2 {
+            return;
+        }
+        let first = scan.prefetched_until.max(page_id) + stride;
+        let last = page_id + window;
+        scan.prefetched_until = last;
+        self.issue_prefetches(state, (first..=last).step_by(stride as usize));
+    }
+
+    fn pin(
+        &self,
+        frame_id: FrameId,
+        page: &Page,
+        access_type: AccessType,
+    ) -> Result<(), Exception> {
+        self.replacer
+            .record_access(frame_id, page.get_page_id(), access_type)?;
+        self.hold(frame_id, page)
+    }
+
+    // Pins a resident page without counting it as an access, so that
+    // flushing does not make a page look hot.
+    fn hold(&self, frame_id: FrameId, page: &Page) -> Result<(), Exception> {
+        self.replacer.set_evictable(frame_id, false)?;
+        page.pin();
+        Ok(())
+    }
+
+    // Takes a frame for the page `page_id` returns, through `strategy`'s
+    // ring if it has one, and records it in the ring. Returns `None` if the
+    // only frames left are being read, which `wait_for_frame` waits for.
+    fn reserve_frame(
+        &self,
+        state: &mut PoolState,
+        strategy: &BufferAccessStrategy,
+        page_id: impl FnOnce() -> PageId,
+    ) -> Result<Option<(Victim, PageId)>, Exception> {
+        let mut ring = strategy.lock_ring()?;
+        let ring_size = self.ring_size(strategy);
+        let Some(victim) = self.acquire_frame_with(state, ring.as_deref(), ring_size)? else {
+            let reading = state
+                .io_pending
+                .iter()
+                .any(|page_id| state.page_table.contains_key(page_id));
+            if state.prefetches.is_empty() && !reading {
+                return Err(Exception::OutOfMemory(
+                    "Every frame of the buffer pool is pinned",
+                ));
+            }
+            return Ok(None);
+        };
+        let page_id = page_id();
+        if let Some(ring) = &mut ring {
+            ring.push(victim.frame_id, page_id, ring_size);
+        }
+        Ok(Some((victim, page_id)))
+    }
+
+    // Waits with the latch released until a frame that was being read may
+    // have become evictable: for a prefetch, or for another thread's read.
+    fn wait_for_frame<'a>(
+        &'a self,
+        state: MutexGuard<'a, PoolState>,
+    ) -> Result<MutexGuard<'a, PoolState>, Exception> {
+        match state.prefetches.keys().next() {
+            Some(&page_id) => self.collect_prefetch(state, page_id),
+            None => Ok(self.io_done.wait(state)?),
+        }
+    }
+
+    // Like `acquire_frame`, but once `ring` is full, takes its next frame
+    // instead if the page it was filled with is still there and can be
+    // evicted.
+    fn acquire_frame_with(
+        &self,
+        state: &mut PoolState,
+        ring: Option<&Ring>,
+        ring_size: usize,
+    ) -> Result<Option<Victim>, Exception> {
+        let Some((frame_id, page_id)) = ring.and_then(|ring| ring.next_slot(ring_size)) else {
+            return self.acquire_frame(state);
+        };
+        let page = &self.pages[frame_id as usize];
+        if state.page_table.get(&page_id) != Some(&frame_id)
+            || state.prefetches.contains_key(&page_id)
+            || state.io_pending.contains(&page_id)
+            || page.get_pin_count() > 0
+        {
+            return self.acquire_frame(state);
+        }
+        self.evict_frame(state, frame_id, true).map(Some)
+    }
+
+    // Like `take_frame`, but when no page can be evicted, collects the
+    // prefetches that have completed and tries again.
+    fn acquire_frame(&self, state: &mut PoolState) -> Result<Option<Victim>, Exception> {
+        if let Some(victim) = self.take_frame(state)? {
+            return Ok(Some(victim));
+        }
+        self.collect_prefetches(state);
+        self.take_frame(state)
+    }
+
+    // Takes a free frame, or evicts a page. Returns `None` if no page can be
+    // evicted.
+    fn take_frame(&self, state: &mut PoolState) -> Result<Option<Victim>, Exception> {
+        if let Some(frame_id) = state.free_list.pop_front() {
+            return Ok(Some(Victim {
+                frame_id,
+                write_back: None,
+                recycle: false,
+            }));
+        }
+        let Some(frame_id) = self.replacer.victim() else {
+            return Ok(None);
+        };
+        self.evict_frame(state, frame_id, false).map(Some)
+    }
+
+    // Like `take_frame`, but only evicts clean pages. Dirty ones keep their
+    // place in the replacer.
+    fn take_clean_frame(&self, state: &mut PoolState) -> Result<Option<FrameId>, Exception> {
+        if let Some(frame_id) = state.free_list.pop_front() {
+            return Ok(Some(frame_id));
+        }
+        let clean = |frame_id: FrameId| !self.pages[frame_id as usize].is_dirty();
+        let Some(frame_id) = self.replacer.evict_if(&clean) else {
+            return Ok(None);
+        };
+        state
+            .page_table
+            .remove(&self.pages[frame_id as usize].get_page_id());
+        Ok(Some(frame_id))
+    }
+
+    // Drops the evictable page of `frame_id`. If it is dirty, schedules its
+    // write-back and keeps it pending until `fill_frame` has waited for the
+    // write; until then the replacer keeps tracking it, pinned, so that a
+    // failed write leaves it where it was. If the write cannot be
+    // scheduled, nothing changes.
+    fn evict_frame(
+        &self,
+        state: &mut PoolState,
+        frame_id: FrameId,
+        recycle: bool,
+    ) -> Result<Victim, Exception> {
+        let page = &self.pages[frame_id as usize];
+        let page_id = page.get_page_id();
+        let mut write_back = None;
+        if page.is_dirty() {
+            let write = self.write_page(page, RequestPriority::Foreground)?;
+            self.replacer.set_evictable(frame_id, false)?;
+            write_back = Some((page_id, write));
+            state.io_pending.insert(page_id);
+        } else {
+            self.forget_frame(frame_id, recycle)?;
+        }
+        state.page_table.remove(&page_id);
+        Ok(Victim {
+            frame_id,
+            write_back,
+            recycle,
+        })
+    }
+
+    // Stops tracking the evictable frame `frame_id` in the replacer: as an
+    // eviction, or when `recycle` is set, as a removal.
+    fn forget_frame(&self, frame_id: FrameId, recycle: bool) -> Result<(), Exception> {
+        if recycle {
+            return self.replacer.remove(frame_id);
+        }
+        match self.replacer.evict_if(&|candidate| candidate == frame_id) {
+            Some(_) => Ok(()),
+            None => Err(Exception::Invalid("Frame is not evictable")),
+        }
+    }
+
+    // Makes the frame of `victim` hold `page_id`, unpinned. The frame is
+    // reserved under the latch, which `state` holds; the latch is released
+    // while the write-back of the evicted page completes and, if `read` is
+    // set, while `page_id` is read into the frame. Threads after either
+    // page wait for the frame meanwhile. If the write-back fails, the
+    // evicted page stays in the frame; if the read fails, the frame is
+    // freed.
+    fn fill_frame<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, PoolState>,
+        victim: Victim,
+        page_id: PageId,
+        read: bool,
+    ) -> Result<MutexGuard<'a, PoolState>, Exception> {
+        let Victim {
+            frame_id,
+            write_back,
+            recycle,
+        } = victim;
+        let page = &self.pages[frame_id as usize];
+        page.reset(page_id);
+        state.page_table.insert(page_id, frame_id);
+        if write_back.is_none() && !read {
+            return Ok(state);
+        }
+        state.io_pending.insert(page_id);
+        drop(state);
+
+        // The frame already holds `page_id`, so the evicted page is not
+        // marked clean.
+        let write_back = write_back
+            .map(|(evicted, write)| (evicted, write.future.wait().map_err(Exception::from)));
+        let read_result = match write_back {
+            Some((_, Err(_))) => Ok(()),
+            _ if read => self.read_page(page_id, page),
+            _ => Ok(()),
+        };
+
+        let mut state = self.latch.lock()?;
+        state.io_pending.remove(&page_id);
+        if let Some((evicted, _)) = &write_back {
+            state.io_pending.remove(evicted);
+        }
+        self.io_done.notify_all();
+        if let Some((evicted, result)) = write_back {
+            self.replacer.set_evictable(frame_id, true)?;
+            if let Err(error) = result {
+                state.page_table.remove(&page_id);
+                page.reset(evicted);
+                page.set_dirty(true);
+                state.page_table.insert(evicted, frame_id);
+                return Err(error);
+            }
+            self.forget_frame(frame_id, recycle)?;
+        }
+        if let Err(error) = read_result {
+            state.page_table.remove(&page_id);
+            page.reset(INVALID_PAGE_ID);
+            state.free_list.push_back(frame_id);
+            return Err(error);
+        }
+        Ok(state)
+    }
+
+    // Waits until no other thread has I/O pending on `page_id`.
+    fn wait_for_io<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, PoolState>,
+        page_id: PageId,
+    ) -> Result<MutexGuard<'a, PoolState>, Exception> {
+        while state.io_pending.contains(&page_id) {
+            state = self.io_done.wait(state)?;
+        }
+        Ok(state)
+    }
+
+    // Schedules a read at prefetch priority for each page that is not
+    // cached, into frames that can be had without waiting. Returns the
+    // number of reads issued.
+    fn issue_prefetches(
+        &self,
+        state: &mut PoolState,
+        page_ids: impl IntoIterator<Item = PageId>,
+    ) -> usize {
+        let mut num_issued = 0;
+        for page_id in page_ids {
+            if page_id < 0
+                || state.page_table.contains_key(&page_id)
+                || state.io_pending.contains(&page_id)
+            {
+                continue;
+            }
+            let Ok(Some(frame_id)) = self.take_clean_frame(state) else {
+                break;
+            };
+            let page = &self.pages[frame_id as usize];
+            let Ok(mut data) = page.write() else {
+                state.free_list.push_back(frame_id);
+                break;
+            };
+            page.reset(page_id);
+            let buffer = mem::replace(&mut *data, PageBuffer::empty());
+            let future = self.disk_scheduler.schedule_async_with_priority(
+                RequestType::Read,
+                page_id,
+                buffer,
+                RequestPriority::Prefetch,
+            );
+            state.page_table.insert(page_id, frame_id);
+            state.prefetches.insert(page_id, future);
+            num_issued += 1;
+        }
+        num_issued
+    }
+
+    // Collects the prefetches that have completed, without waiting for the
+    // others.
+    fn collect_prefetches(&self, state: &mut PoolState) {
+        let mut completed = Vec::new();
+        state
+            .prefetches
+            .retain(|&page_id, future| match future.try_wait() {
+                Some(result) => {
+                    completed.push((page_id, result));
+                    false
+                }
+                None => true,
+            });
+        for (page_id, result) in completed {
+            let _ = self.install_prefetch(state, page_id, result);
+        }
+    }
+
+    // Collects the prefetch of `page_id`, if any. If it has not completed,
+    // waits for it with the latch released; threads after the page wait
+    // for it meanwhile. A failed read only frees the frame.
+    fn collect_prefetch<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, PoolState>,
+        page_id: PageId,
+    ) -> Result<MutexGuard<'a, PoolState>, Exception> {
+        let Some(mut future) = state.prefetches.remove(&page_id) else {
+            return Ok(state);
+        };
+        let result = match future.try_wait() {
+            Some(result) => result,
+            None => {
+                state.io_pending.insert(page_id);
+                drop(state);
+                let result = future.wait();
+                state = self.latch.lock()?;
+                state.io_pending.remove(&page_id);
+                self.io_done.notify_all();
+                result
+            }
+        };
+        let _ = self.install_prefetch(&mut state, page_id, result);
+        Ok(state)
+    }
+
+    // Installs the page a completed prefetch read as an evictable, unpinned
+    // page; if the read failed, frees its frame.
+    fn install_prefetch(
+        &self,
+        state: &mut PoolState,
+        page_id: PageId,
+        result: Result<PageBuffer, DiskError>,
+    ) -> Result<(), Exception> {
+        let frame_id = state.page_table[&page_id];
+        let page = &self.pages[frame_id as usize];
+        let mut data = page.write()?;
+        match result {
+            Ok(buffer) => {
+                *data = buffer;
+                self.replacer
+                    .record_access(frame_id, page_id, AccessType::Scan)?;
+                self.replacer.set_evictable(frame_id, true)
+            }
+            Err(error) => {
+                *data = reusable_buffer(error.data);
+                state.page_table.remove(&page_id);
+                page.reset(INVALID_PAGE_ID);
+                state.free_list.push_back(frame_id);
+                Err(error.error)
+            }
+        }
+    }
+
+    // Writes the dirty pages `select` picks. An unpinned page is copied for
+    // its write under the latch, which is retaken for every
+    // `WRITE_BACK_BATCH` pages, and stays evictable: it is dirty until its
+    // write succeeds, so evicting it meanwhile writes it again. A pinned
+    // page may be latched by a caller waiting for the pool, so it is pinned
+    // once more and copied after the latch is released. The writes are
+    // awaited at the end.
+    fn write_back(
+        &self,
+        priority: RequestPriority,
+        select: impl Fn(&Page) -> bool,
+    ) -> Result<usize, Exception> {
+        let page_ids: Vec<PageId> = self.latch.lock()?.page_table.keys().copied().collect();
+        let mut writes = Vec::new();
+        let mut held = Vec::new();
+        let mut result = Ok(());
+        for batch in page_ids.chunks(WRITE_BACK_BATCH) {
+            let state = match self.latch.lock() {
+                Ok(state) => state,
+                Err(error) => {
+                    result = Err(error.into());
+                    break;
+                }
+            };
+            for page_id in batch {
+                let Some(&frame_id) = state.page_table.get(page_id) else {
+                    continue;
+                };
+                let page = &self.pages[frame_id as usize];
+                if !page.is_dirty() || !select(page) || state.io_pending.contains(page_id) {
+                    continue;
+                }
+                if page.get_pin_count() > 0 {
+                    match self.hold(frame_id, page) {
+                        Ok(()) => held.push(page.clone()),
+                        Err(error) => result = Err(error),
+                    }
+                    continue;
+                }
+                match self.write_page(page, priority) {
+                    Ok(write) => writes.push((&**page, write)),
+                    Err(error) => result = Err(error),
+                }
+            }
+        }
+        for page in &held {
+            match self.write_page(page, priority) {
+                Ok(write) => writes.push((&**page, write)),
+                Err(error) => result = Err(error),
+            }
+        }
+        let num_pages = writes.len();
+        for (page, write) in writes {
+            result = result.and(write.wait(page));
+        }
+        for page in &held {
+            if let Err(error) = self.unpin_page(page.get_page_id(), false) {
+                result = result.and(Err(error));
+            }
+        }
+        result.map(|_| num_pages)
+    }
+
+    // Writes `data`, which the caller holds latched, as the contents of the
+    // pinned `page` and waits for the write. Flushes a page through a guard,
+    // whose latch `flush_page` would wait for.
+    pub(crate) fn flush_latched_page(&self, page: &Page, data: &[u8]) -> Result<(), Exception> {
+        self.write_copy(page, data, RequestPriority::Foreground)
+            .wait(page)
+    }
+
+    // Schedules a write of a copy of `page`. The page stays dirty until
+    // `PageWrite::wait` sees the write succeed.
+    fn write_page(&self, page: &Page, priority: RequestPriority) -> Result<PageWrite, Exception> {
+        Ok(self.write_copy(page, &page.read()?, priority))
+    }
+
+    // Schedules a write of `data`, which the caller holds latched, as the
+    // contents of `page`. The dirty state is taken under the latch, so a
+    // modification made after the copy keeps the page dirty.
+    fn write_copy(&self, page: &Page, data: &[u8], priority: RequestPriority) -> PageWrite {
+        let dirty_state = page.get_dirty_state();
+        let future = self.disk_scheduler.schedule_async_with_priority(
+            RequestType::Write,
+            page.get_page_id(),
+            PageBuffer::from_slice(data),
+            priority,
+        );
+        PageWrite {
+            future,
+            dirty_state,
+        }
+    }
+
+    fn read_page(&self, page_id: PageId, page: &Page) -> Result<(), Exception> {
+        let mut data = page.write()?;
+        let buffer = mem::replace(&mut *data, PageBuffer::empty());
+        match self
+            .disk_scheduler
+            .schedule_async(RequestType::Read, page_id, buffer)
+            .wait()
+        {
+            Ok(buffer) => *data = buffer,
+            Err(error) => {
+                *data = reusable_buffer(error.data);
+                return Err(error.error);
+            }
+        }
+        Ok(())
+    }
+}
+
+// The buffer a failed read handed back, or a new one if the request was
+// dropped with it.
+fn reusable_buffer(buffer: PageBuffer) -> PageBuffer {
+    match buffer.is_empty() {
+        true => PageBuffer::new(),
+        false => buffer,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::buffer_access_strategy::AccessStrategy;
+    use crate::common::temp_db::TempDb;
+    use crate::storage::disk::disk_manager::{DiskManager, open_page_file};
+    use std::fs;
+
+    fn setup(db_name: &str, options: BufferPoolOptions) -> (BufferPoolManager, TempDb) {
+        let db = TempDb::new(db_name);
+        let disk_manager = Arc::new(DiskManager::new(db.get_path().to_path_buf()).unwrap());
+        let disk_scheduler = Arc::new(DiskScheduler::new(disk_manager));
+        (BufferPoolManager::with_options(disk_scheduler, options), db)
+    }
+
+    fn small_pool(pool_size: usize) -> BufferPoolOptions {
+        BufferPoolOptions {
+            pool_size,
+            ..Default::default()
+        }
+    }
+
+    // Makes writes to the page file of `disk_manager` fail until the
+    // returned handle is dropped, by putting a read-only descriptor in
+    // place of the file's.
+    #[cfg(unix)]
+    fn fail_page_writes(disk_manager: &DiskManager) -> FailingWrites<'_> {
+        let file = fs::File::open(disk_manager.get_db_file_name()).unwrap();
+        replace_page_file(disk_manager, &file);
+        FailingWrites { disk_manager }
+    }
+
+    #[cfg(unix)]
+    struct FailingWrites<'a> {
+        disk_manager: &'a DiskManager,
+    }
+
+    #[cfg(unix)]
+    impl Drop for FailingWrites<'_> {
+        fn drop(&mut self) {
+            let disk_manager = self.disk_manager;
+            let (file, _) =
+                open_page_file(disk_manager.get_db_file_name(), disk_manager.is_direct_io())
+                    .unwrap();
+            replace_page_file(disk_manager, &file);
+        }
+    }
+
+    #[cfg(unix)]
+    fn replace_page_file(disk_manager: &DiskManager, file: &fs::File) {
+        use std::os::fd::AsRawFd;
+
+        let fd = disk_manager.page_file().as_raw_fd();
+        assert_eq!(unsafe { libc::dup2(file.as_raw_fd(), fd) }, fd);
+    }
+
+    #[test]
+    fn test_pages_survive_eviction() {
+        let (bpm, _db) = setup("test_bpm_eviction.db", small_pool(4));
+        let mut page_ids = Vec::new();
+        for i in 0..16u8 {
+            let page = bpm.new_page().unwrap();
+            page.write().unwrap()[..3].copy_from_slice(&[i, i, i]);
+            page_ids.push(page.get_page_id());
+            bpm.unpin_page(page.get_page_id(), true).unwrap();
+        }
+        for (i, &page_id) in page_ids.iter().enumerate().rev() {
+            let page = bpm.fetch_page(page_id, AccessType::Lookup).unwrap();
+            assert_eq!(page.read().unwrap()[..3], [i as u8; 3]);
+            bpm.unpin_page(page_id, false).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_pinned_pages_are_not_evicted() {
+        let (bpm, _db) = setup("test_bpm_pinned.db", small_pool(3));
+        let pages: Vec<_> = (0..3).map(|_| bpm.new_page().unwrap()).collect();
+        assert!(matches!(bpm.new_page(), Err(Exception::OutOfMemory(_))));
+        assert!(bpm.fetch_page(100, AccessType::Lookup).is_err());
+
+        // Pinning a page twice takes two unpins to release it.
+        let page_id = pages[1].get_page_id();
+        bpm.fetch_page(page_id, AccessType::Lookup).unwrap();
+        assert_eq!(bpm.get_pin_count(page_id), Some(2));
+        bpm.unpin_page(page_id, false).unwrap();
+        assert!(bpm.new_page().is_err());
+        bpm.unpin_page(page_id, false).unwrap();
+        assert!(bpm.unpin_page(page_id, false).is_err());
+
+        let page = bpm.new_page().unwrap();
+        assert_eq!(bpm.get_pin_count(page_id), None);
+        assert_eq!(bpm.get_pin_count(page.get_page_id()), Some(1));
+    }
+
+    #[test]
+    fn test_flush_pages() {
+        let (bpm, _db) = setup("test_bpm_flush.db", small_pool(8));
+        let disk_manager = bpm.get_disk_scheduler().get_disk_manager().clone();
+        let page = bpm.new_page().unwrap();
+        let page_id = page.get_page_id();
+        page.write().unwrap()[..5].copy_from_slice(b"hello");
+        bpm.flush_page(page_id).unwrap();
+        assert!(!page.is_dirty());
+        assert_eq!(bpm.get_pin_count(page_id), Some(1));
+        bpm.unpin_page(page_id, false).unwrap();
+
+        let mut data = PageBuffer::new();
+        disk_manager.read_page(page_id, &mut data).unwrap();
+        assert_eq!(&data[..5], b"hello");
+
+        for _ in 0..4 {
+            let page = bpm.new_page().unwrap();
+            bpm.unpin_page(page.get_page_id(), true).unwrap();
+        }
+        let num_writes = disk_manager.get_num_writes().unwrap();
+        bpm.flush_all_pages().unwrap();
+        // Only the four dirty pages are written.
+        assert_eq!(disk_manager.get_num_writes().unwrap(), num_writes + 4);
+        assert!(bpm.flush_page(1000).is_err());
+    }
+
+    #[test]
+    fn test_flush_leaves_pins_alone() {
+        let (bpm, _db) = setup("test_bpm_flush_pins.db", small_pool(4));
+        let disk_manager = bpm.get_disk_scheduler().get_disk_manager().clone();
+        let pinned = bpm.new_page().unwrap();
+        let unpinned: Vec<PageId> = (0..3)
+            .map(|_| bpm.new_page_guarded().unwrap().get_page_id())
+            .collect();
+        let num_writes = disk_manager.get_num_writes().unwrap();
+        bpm.flush_all_pages().unwrap();
+        assert_eq!(disk_manager.get_num_writes().unwrap(), num_writes + 4);
+        assert_eq!(bpm.get_pin_count(pinned.get_page_id()), Some(1));
+        for &page_id in &unpinned {
+            assert_eq!(bpm.get_pin_count(page_id), Some(0));
+        }
+        // The flushed pages can be evicted for new ones.
+        for _ in 0..3 {
+            bpm.new_page().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_delete_page() {
+        let (bpm, _db) = setup("test_bpm_delete.db", small_pool(2));
+        let page = bpm.new_page().unwrap();
+        let page_id = page.get_page_id();
+        bpm.flush_page(page_id).unwrap();
+        assert!(bpm.delete_page(page_id).is_err());
+        bpm.unpin_page(page_id, false).unwrap();
+        bpm.delete_page(page_id).unwrap();
+        assert_eq!(bpm.get_pin_count(page_id), None);
+        assert!(bpm.fetch_page(page_id, AccessType::Lookup).is_err());
+
+        // The freed frame is reused.
+        let _first = bpm.new_page().unwrap();
+        let _second = bpm.new_page().unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_misses() {
+        let (bpm, _db) = setup("test_bpm_concurrent.db", small_pool(8));
+        let page_ids: Vec<PageId> = (0..32)
+            .map(|_| bpm.new_page_guarded().unwrap().get_page_id())
+            .collect();
+        // Every page keeps being evicted dirty and read back by one thread
+        // while the others fetch theirs; a read that overtook the write-back
+        // would lose an update.
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let (bpm, page_ids) = (&bpm, &page_ids);
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        for &page_id in page_ids.iter().skip(thread).step_by(4) {
+                            let mut guard =
+                                bpm.fetch_page_write(page_id, AccessType::Lookup).unwrap();
+                            guard[0] += 1;
+                            drop(guard);
+                            let guard = bpm
+                                .fetch_page_read(page_ids[0], AccessType::Lookup)
+                                .unwrap();
+                            drop(guard);
+                        }
+                    }
+                });
+            }
+        });
+        for &page_id in &page_ids {
+            assert_eq!(
+                bpm.fetch_page_read(page_id, AccessType::Lookup).unwrap()[0],
+                20
+            );
+            assert_eq!(bpm.get_pin_count(page_id), Some(0));
+        }
+    }
+
+    #[test]
+    fn test_reopen_allocates_new_page_ids() {
+        let (bpm, db) = setup("test_bpm_reopen.db", small_pool(4));
+        for i in 0..6u8 {
+            let mut guard = bpm.new_page_guarded().unwrap();
+            guard[0] = i;
+        }
+        bpm.flush_all_pages().unwrap();
+        drop(bpm);
+
+        let disk_manager = Arc::new(DiskManager::new(db.get_path().to_path_buf()).unwrap());
+        let disk_scheduler = Arc::new(DiskScheduler::new(disk_manager));
+        let bpm = BufferPoolManager::with_options(disk_scheduler.clone(), small_pool(4));
+        {
+            let mut guard = bpm.new_page_guarded().unwrap();
+            assert_eq!(guard.get_page_id(), 6);
+            guard[0] = 100;
+        }
+        bpm.flush_all_pages().unwrap();
+        for page_id in 0..6 {
+            let guard = bpm.fetch_page_read(page_id, AccessType::Lookup).unwrap();
+            assert_eq!(guard[0], page_id as u8);
+        }
+        // An instance of a sharded pool starts at its next own page id.
+        let instance = BufferPoolManager::for_instance(disk_scheduler, small_pool(4), 1, 3);
+        assert_eq!(instance.new_page().unwrap().get_page_id(), 7);
+        drop((bpm, instance));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failed_write_back_keeps_the_page() {
+        let options = BufferPoolOptions {
+            pool_size: 2,
+            replacer_policy: ReplacerPolicy::LruK,
+            ..Default::default()
+        };
+        let (bpm, _db) = setup("test_bpm_failed_write_back.db", options);
+        let disk_manager = bpm.get_disk_scheduler().get_disk_manager().clone();
+        let first = bpm.new_page().unwrap().get_page_id();
+        let page = bpm.new_page().unwrap();
+        page.write().unwrap()[..3].copy_from_slice(b"abc");
+        let second = page.get_page_id();
+        bpm.fetch_page(first, AccessType::Lookup).unwrap();
+        bpm.unpin_page(first, false).unwrap();
+        bpm.unpin_page(first, false).unwrap();
+        bpm.unpin_page(second, true).unwrap();
+
+        // The least used page stays the victim however often its
+        // write-back fails.
+        for _ in 0..3 {
+            let failing = fail_page_writes(&disk_manager);
+            assert!(bpm.new_page().is_err());
+            drop(failing);
+            assert_eq!(bpm.get_pin_count(second), Some(0));
+            assert_eq!(bpm.get_pin_count(first), Some(0));
+        }
+        let page = bpm.new_page().unwrap();
+        assert_eq!(bpm.get_pin_count(second), None);
+        assert_eq!(bpm.get_pin_count(first), Some(0));
+        bpm.unpin_page(page.get_page_id(), false).unwrap();
+
+        let page = bpm.fetch_page(second, AccessType::Lookup).unwrap();
+        assert_eq!(&page.read().unwrap()[..3], b"abc");
+        bpm.unpin_page(second, false).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failed_flush_keeps_the_page_dirty() {
+        let (bpm, _db) = setup("test_bpm_failed_flush.db", small_pool(2));
+        let disk_manager = bpm.get_disk_scheduler().get_disk_manager().clone();
+        let page = bpm.new_page().unwrap();
+        let page_id = page.get_page_id();
+        page.write().unwrap()[..3].copy_from_slice(b"abc");
+        bpm.unpin_page(page_id, true).unwrap();
+
+        let failing = fail_page_writes(&disk_manager);
+        assert!(bpm.flush_page(page_id).is_err());
+        assert!(page.is_dirty());
+        assert!(bpm.flush_all_pages().is_err());
+        assert!(page.is_dirty());
+        assert!(
+            bpm.flush_unpinned_pages(RequestPriority::Background)
+                .is_err()
+        );
+        assert!(page.is_dirty());
+        drop(failing);
+
+        // Evicting the page writes it back.
+        for _ in 0..2 {
+            let page = bpm.new_page().unwrap();
+            bpm.unpin_page(page.get_page_id(), false).unwrap();
+        }
+        assert_eq!(bpm.get_pin_count(page_id), None);
+        let page = bpm.fetch_page(page_id, AccessType::Lookup).unwrap();
+        assert_eq!(&page.read().unwrap()[..3], b"abc");
+        assert!(!page.is_dirty());
+        bpm.unpin_page(page_id, false).unwrap();
+    }
+
+    #[test]
+    fn test_every_policy() {
+        let policies = [
+            ReplacerPolicy::Arc,
+            ReplacerPolicy::Lru,
+            ReplacerPolicy::LruK,
+            ReplacerPolicy::Clock,
+            ReplacerPolicy::TwoQueue,
+        ];
+        for policy in policies {
+            let options = BufferPoolOptions {
+                pool_size: 4,
+                replacer_policy: policy,
+                ..Default::default()
+            };
+            let (bpm, _db) = setup("test_bpm_policies.db", options);
+            for i in 0..32u8 {
+                let page = bpm.new_page().unwrap();
+                page.write().unwrap()[0] = i;
+                bpm.unpin_page(page.get_page_id(), true).unwrap();
+                // Keep re-reading an older page, as a hot page would be.
+                let page = bpm
+                    .fetch_page(PageId::from(i / 2), AccessType::Lookup)
+                    .unwrap();
+                assert_eq!(page.read().unwrap()[0], i / 2, "{policy:?}");
+                bpm.unpin_page(page.get_page_id(), false).unwrap();
+            }
+        }
+    }
+
+    // Writes `num_pages` pages holding their id and returns a pool on the
+    // same disk with none of them cached.
+    fn cold_pool(
+        db_name: &str,
+        num_pages: usize,
+        options: BufferPoolOptions,
+    ) -> (BufferPoolManager, TempDb) {
+        let (bpm, db) = setup(db_name, small_pool(num_pages));
+        for _ in 0..num_pages {
+            let mut guard = bpm.new_page_guarded().unwrap();
+            let page_id = guard.get_page_id();
+            guard[0] = page_id as u8;
+        }
+        bpm.flush_all_pages().unwrap();
+        let disk_scheduler = bpm.get_disk_scheduler().clone();
+        (BufferPoolManager::with_options(disk_scheduler, options), db)
+    }
+
+    #[test]
+    fn test_prefetch_pages() {
+        let options = BufferPoolOptions {
+            pool_size: 6,
+            read_ahead_window: 0,
+            ..Default::default()
+        };
+        let (bpm, _db) = cold_pool("test_bpm_prefetch.db", 8, options);
+        assert_eq!(bpm.prefetch_pages(&[0, 1, 2, 3, 3]).unwrap(), 4);
+        assert_eq!(bpm.prefetch_pages(&[1, 2]).unwrap(), 0);
+        assert_eq!(bpm.get_pin_count(3), Some(0));
+        for page_id in 0..4 {
+            let guard = bpm.fetch_page_read(page_id, AccessType::Scan).unwrap();
+            assert_eq!(guard[0], page_id as u8);
+        }
+
+        // A page that does not exist is not cached, and fetching it fails.
+        assert_eq!(bpm.prefetch_pages(&[100]).unwrap(), 1);
+        assert!(bpm.fetch_page(100, AccessType::Lookup).is_err());
+        assert_eq!(bpm.get_pin_count(100), None);
+
+        // Prefetching never takes pinned frames.
+        let pinned: Vec<_> = (4..8)
+            .map(|page_id| bpm.fetch_page_read(page_id, AccessType::Lookup).unwrap())
+            .collect();
+        let _first = bpm.fetch_page_read(0, AccessType::Lookup).unwrap();
+        let _second = bpm.fetch_page_read(1, AccessType::Lookup).unwrap();
+        assert_eq!(bpm.prefetch_pages(&[2, 3]).unwrap(), 0);
+        drop(pinned);
+    }
+
+    #[test]
+    fn test_prefetched_frames_are_reused() {
+        let options = BufferPoolOptions {
+            pool_size: 4,
+            read_ahead_window: 0,
+            ..Default::default()
+        };
+        let (bpm, _db) = cold_pool("test_bpm_prefetch_reuse.db", 8, options);
+        assert_eq!(bpm.prefetch_pages(&[0, 1, 2, 3]).unwrap(), 4);
+        // Every frame is being prefetched, so new pages wait for the reads.
+        let pages: Vec<_> = (0..4).map(|_| bpm.new_page_guarded().unwrap()).collect();
+        assert!(matches!(bpm.new_page(), Err(Exception::OutOfMemory(_))));
+        drop(pages);
+        for page_id in 0..8 {
+            let guard = bpm.fetch_page_read(page_id, AccessType::Lookup).unwrap();
+            assert_eq!(guard[0], page_id as u8);
+        }
+    }
+
+    #[test]
+    fn test_sequential_read_ahead() {
+        let options = BufferPoolOptions {
+            pool_size: 32,
+            read_ahead_window: 8,
+            ..Default::default()
+        };
+        let (bpm, _db) = cold_pool("test_bpm_read_ahead.db", 32, options);
+        for page_id in 0..2 {
+            drop(bpm.fetch_page_read(page_id, AccessType::Scan).unwrap());
+        }
+        assert_eq!(bpm.get_pin_count(2), None);
+
+        // The third page in order starts read-ahead of the next eight.
+        drop(bpm.fetch_page_read(2, AccessType::Scan).unwrap());
+        assert_eq!(bpm.get_pin_count(10), Some(0));
+        assert_eq!(bpm.get_pin_count(11), None);
+
+        // It is topped up once half of the window has been consumed.
+        for page_id in 3..6 {
+            drop(bpm.fetch_page_read(page_id, AccessType::Scan).unwrap());
+        }
+        assert_eq!(bpm.get_pin_count(11), None);
+        drop(bpm.fetch_page_read(6, AccessType::Scan).unwrap());
+        assert_eq!(bpm.get_pin_count(14), Some(0));
+        for page_id in 7..15 {
+            let guard = bpm.fetch_page_read(page_id, AccessType::Scan).unwrap();
+            assert_eq!(guard[0], page_id as u8);
+        }
+
+        // Random accesses do not read ahead.
+        drop(bpm.fetch_page_read(30, AccessType::Lookup).unwrap());
+        drop(bpm.fetch_page_read(26, AccessType::Lookup).unwrap());
+        assert_eq!(bpm.get_pin_count(27), None);
+        assert_eq!(bpm.get_pin_count(31), None);
+    }
+
+    #[test]
+    fn test_bulk_read_keeps_hot_pages() {
+        for policy in [
+            ReplacerPolicy::Arc,
+            ReplacerPolicy::Lru,
+            ReplacerPolicy::Clock,
+        ] {
+            let options = BufferPoolOptions {
+                pool_size: 16,
+                replacer_policy: policy,
+                read_ahead_window: 0,
+            };
+            let scan = |strategy: &BufferAccessStrategy| {
+                let (bpm, _db) = cold_pool("test_bpm_bulk_read.db", 64, options);
+                for _ in 0..2 {
+                    for page_id in 0..8 {
+                        drop(bpm.fetch_page_read(page_id, AccessType::Lookup).unwrap());
+                    }
+                }
+                for page_id in 8..64 {
+                    let guard = bpm
+                        .fetch_page_read_with_strategy(page_id, AccessType::Scan, strategy)
+                        .unwrap();
+                    assert_eq!(guard[0], page_id as u8, "{policy:?}");
+                }
+                let hot = (0..8).filter(|&id| bpm.get_pin_count(id).is_some());
+                let scanned = (8..64).filter(|&id| bpm.get_pin_count(id).is_some());
+                (hot.count(), scanned.count())
+            };
+
+            // The scan recycles a ring of two frames, an eighth of the pool.
+            let ring = BufferAccessStrategy::new(AccessStrategy::BulkRead);
+            assert_eq!(scan(&ring), (8, 2), "{policy:?}");
+            // Without it, policies that favour recency lose the hot pages.
+            if policy != ReplacerPolicy::Arc {
+                let (hot, _) = scan(&BufferAccessStrategy::default());
+                assert!(hot < 8, "{policy:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_bulk_write_ring() {
+        let (bpm, _db) = setup("test_bpm_bulk_write.db", small_pool(16));
+        let hot: Vec<PageId> = (0..4)
+            .map(|_| bpm.new_page_guarded().unwrap().get_page_id())
+            .collect();
+        let strategy = BufferAccessStrategy::with_ring_size(AccessStrategy::BulkWrite, 4);
+        let mut loaded = Vec::new();
+        let mut held = None;
+        for i in 0..40u8 {
+            let mut guard = bpm.new_page_guarded_with_strategy(&strategy).unwrap();
+            guard[0] = i;
+            loaded.push(guard.get_page_id());
+            // A pinned page is skipped when its frame comes round.
+            if i == 5 {
+                held = Some(guard);
+            }
+        }
+        for &page_id in &hot {
+            assert_eq!(bpm.get_pin_count(page_id), Some(0));
+        }
+        let held = held.unwrap();
+        assert_eq!(bpm.get_pin_count(held.get_page_id()), Some(1));
+        assert_eq!(held[0], 5);
+        drop(held);
+
+        // Pages pushed out of the ring were written back.
+        for (i, &page_id) in loaded.iter().enumerate() {
+            let guard = bpm.fetch_page_read(page_id, AccessType::Lookup).unwrap();
+            assert_eq!(guard[0], i as u8);
+        }
+    }
+}
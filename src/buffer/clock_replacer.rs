@@ -0,0 +1,187 @@
+use std::sync::Mutex;
+
+use crate::buffer::replacer::{AccessType, Replacer, check_frame};
+use crate::common::{
+    config::{FrameId, PageId},
+    exception::Exception,
+};
+
+#[derive(Clone, Copy)]
+struct ClockFrame {
+    page_id: PageId,
+    referenced: bool,
+    is_evictable: bool,
+}
+
+/// Second-chance replacement. A hand sweeps the frames in order; an access
+/// sets a frame's reference bit, and the hand clears the bit instead of
+/// evicting, so only frames untouched for a full sweep are evicted.
+pub struct ClockReplacer {
+    replacer_size: usize,
+    latch: Mutex<ClockState>,
+}
+
+struct ClockState {
+    frames: Vec<Option<ClockFrame>>,
+    hand: usize,
+    curr_size: usize,
+}
+
+impl ClockReplacer {
+    /// Creates a replacer for the frames `0..num_frames`.
+    pub fn new(num_frames: usize) -> Self {
+        Self {
+            replacer_size: num_frames,
+            latch: Mutex::new(ClockState {
+                frames: vec![None; num_frames],
+                hand: 0,
+                curr_size: 0,
+            }),
+        }
+    }
+}
+
+impl Replacer for ClockReplacer {
+    fn record_access(
+        &self,
+        frame_id: FrameId,
+        page_id: PageId,
+        _access_type: AccessType,
+    ) -> Result<(), Exception> {
+        check_frame(frame_id, self.replacer_size)?;
+        let mut state = self.latch.lock()?;
+        let frame = state.frames[frame_id as usize].get_or_insert(ClockFrame {
+            page_id,
+            referenced: false,
+            is_evictable: false,
+        });
+        if frame.page_id != page_id {
+            return Err(Exception::Invalid("Frame already holds another page"));
+        }
+        frame.referenced = true;
+        Ok(())
+    }
+
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> Result<(), Exception> {
+        check_frame(frame_id, self.replacer_size)?;
+        let mut state = self.latch.lock()?;
+        let frame = state.frames[frame_id as usize]
+            .as_mut()
+            .ok_or(Exception::Invalid("Frame is not tracked by the replacer"))?;
+        if frame.is_evictable == set_evictable {
+            return Ok(());
+        }
+        frame.is_evictable = set_evictable;
+        match set_evictable {
+            true => state.curr_size += 1,
+            false => state.curr_size -= 1,
+        }
+        Ok(())
+    }
+
+    fn evict_if(&self, filter: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        let mut guard = self.latch.lock().ok()?;
+        let state = &mut *guard;
+        if state.curr_size == 0 {
+            return None;
+        }
+        // Two sweeps suffice: the first clears every reference bit.
+        let num_frames = state.frames.len();
+        for _ in 0..2 * num_frames {
+            let hand = state.hand;
+            state.hand = (hand + 1) % num_frames;
+            match &mut state.frames[hand] {
+                Some(frame) if !frame.is_evictable || !filter(hand as FrameId) => {}
+                Some(frame) if frame.referenced => frame.referenced = false,
+                Some(_) => {
+                    state.frames[hand] = None;
+                    state.curr_size -= 1;
+                    return Some(hand as FrameId);
+                }
+                None => {}
+            }
+        }
+        None
+    }
+
+    /// The hand would clear the bits of the referenced frames it passes, so
+    /// the victim is the first unreferenced evictable frame from the hand,
+    /// or else the first evictable one.
+    fn victim(&self) -> Option<FrameId> {
+        let state = self.latch.lock().ok()?;
+        let num_frames = state.frames.len();
+        let evictable = || {
+            (0..num_frames)
+                .map(|offset| (state.hand + offset) % num_frames)
+                .filter_map(|index| Some((index, state.frames[index]?)))
+                .filter(|(_, frame)| frame.is_evictable)
+        };
+        evictable()
+            .find(|(_, frame)| !frame.referenced)
+            .or_else(|| evictable().next())
+            .map(|(index, _)| index as FrameId)
+    }
+
+    fn remove(&self, frame_id: FrameId) -> Result<(), Exception> {
+        if check_frame(frame_id, self.replacer_size).is_err() {
+            return Ok(());
+        }
+        let mut guard = self.latch.lock()?;
+        let state = &mut *guard;
+        let slot = &mut state.frames[frame_id as usize];
+        match slot {
+            Some(frame) if !frame.is_evictable => {
+                Err(Exception::Invalid("Cannot remove a non-evictable frame"))
+            }
+            Some(_) => {
+                *slot = None;
+                state.curr_size -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.latch.lock().map_or(0, |state| state.curr_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(replacer: &ClockReplacer, frame_id: FrameId) {
+        replacer
+            .record_access(frame_id, frame_id, AccessType::Lookup)
+            .unwrap();
+        replacer.set_evictable(frame_id, true).unwrap();
+    }
+
+    #[test]
+    fn test_second_chance() {
+        let replacer = ClockReplacer::new(3);
+        for frame_id in 0..3 {
+            access(&replacer, frame_id);
+        }
+        // Every bit is set: the first sweep clears them and frame 0 goes.
+        assert_eq!(replacer.evict(), Some(0));
+        access(&replacer, 0);
+        access(&replacer, 2);
+        // Frame 1 was swept and not used since; frames 0 and 2 get a second
+        // chance, and the hand reaches 2 first.
+        assert_eq!(replacer.evict(), Some(1));
+        assert_eq!(replacer.evict(), Some(2));
+        assert_eq!(replacer.evict(), Some(0));
+        assert_eq!(replacer.evict(), None);
+    }
+
+    #[test]
+    fn test_skips_pinned_frames() {
+        let replacer = ClockReplacer::new(2);
+        replacer.record_access(0, 0, AccessType::Lookup).unwrap();
+        access(&replacer, 1);
+        assert_eq!(replacer.evict(), Some(1));
+        assert_eq!(replacer.evict(), None);
+    }
+}
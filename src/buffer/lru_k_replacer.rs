@@ -0,0 +1,266 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use crate::buffer::replacer::{AccessType, Replacer, check_frame};
+use crate::common::{
+    config::{FrameId, LRUK_REPLACER_K, PageId},
+    exception::Exception,
+};
+
+struct LruKNode {
+    page_id: PageId,
+    // Timestamps of the last `k` accesses, oldest first.
+    history: VecDeque<u64>,
+    is_evictable: bool,
+}
+
+/// LRU-K replacement. Evicts the frame whose k-th most recent access lies
+/// furthest in the past. Frames accessed fewer than k times count as
+/// infinitely distant and go first, least recently used first.
+pub struct LruKReplacer {
+    replacer_size: usize,
+    k: usize,
+    latch: Mutex<LruKState>,
+}
+
+struct LruKState {
+    current_timestamp: u64,
+    curr_size: usize,
+    node_store: HashMap<FrameId, LruKNode>,
+}
+
+impl LruKReplacer {
+    /// Creates a replacer for the frames `0..num_frames` that looks at the
+    /// last `LRUK_REPLACER_K` accesses.
+    pub fn new(num_frames: usize) -> Self {
+        Self::with_k(num_frames, LRUK_REPLACER_K)
+    }
+
+    pub fn with_k(num_frames: usize, k: usize) -> Self {
+        Self {
+            replacer_size: num_frames,
+            k: k.max(1),
+            latch: Mutex::new(LruKState {
+                current_timestamp: 0,
+                curr_size: 0,
+                node_store: HashMap::new(),
+            }),
+        }
+    }
+}
+
+impl Replacer for LruKReplacer {
+    fn record_access(
+        &self,
+        frame_id: FrameId,
+        page_id: PageId,
+        _access_type: AccessType,
+    ) -> Result<(), Exception> {
+        check_frame(frame_id, self.replacer_size)?;
+        let mut state = self.latch.lock()?;
+        let timestamp = state.current_timestamp;
+        state.current_timestamp += 1;
+
+        let node = state
+            .node_store
+            .entry(frame_id)
+            .or_insert_with(|| LruKNode {
+                page_id,
+                history: VecDeque::new(),
+                is_evictable: false,
+            });
+        if node.page_id != page_id {
+            return Err(Exception::Invalid("Frame already holds another page"));
+        }
+        if node.history.len() == self.k {
+            node.history.pop_front();
+        }
+        node.history.push_back(timestamp);
+        Ok(())
+    }
+
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> Result<(), Exception> {
+        check_frame(frame_id, self.replacer_size)?;
+        let mut state = self.latch.lock()?;
+        let node = state
+            .node_store
+            .get_mut(&frame_id)
+            .ok_or(Exception::Invalid("Frame is not tracked by the replacer"))?;
+        if node.is_evictable == set_evictable {
+            return Ok(());
+        }
+        node.is_evictable = set_evictable;
+        match set_evictable {
+            true => state.curr_size += 1,
+            false => state.curr_size -= 1,
+        }
+        Ok(())
+    }
+
+    /// Evicts the frame with the largest backward k-distance and forgets
+    /// its history.
+    fn evict_if(&self, filter: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        let mut state = self.latch.lock().ok()?;
+        let frame_id = state.select(self.k, filter)?;
+        state.node_store.remove(&frame_id);
+        state.curr_size -= 1;
+        Some(frame_id)
+    }
+
+    fn victim(&self) -> Option<FrameId> {
+        self.latch.lock().ok()?.select(self.k, &|_| true)
+    }
+
+    fn remove(&self, frame_id: FrameId) -> Result<(), Exception> {
+        let mut state = self.latch.lock()?;
+        let Some(node) = state.node_store.get(&frame_id) else {
+            return Ok(());
+        };
+        if !node.is_evictable {
+            return Err(Exception::Invalid("Cannot remove a non-evictable frame"));
+        }
+        state.node_store.remove(&frame_id);
+        state.curr_size -= 1;
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        self.latch.lock().map_or(0, |state| state.curr_size)
+    }
+}
+
+impl LruKState {
+    // Frames with fewer than k accesses sort first, by their last access;
+    // the rest by their k-th most recent access.
+    fn select(&self, k: usize, filter: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        self.node_store
+            .iter()
+            .filter(|&(&frame_id, node)| node.is_evictable && filter(frame_id))
+            .min_by_key(|(_, node)| match node.history.len() < k {
+                true => (0, node.history.back().copied()),
+                false => (1, node.history.front().copied()),
+            })
+            .map(|(&frame_id, _)| frame_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Records an access of `frame_id`, holding page `frame_id`, and unpins it.
+    fn access(replacer: &LruKReplacer, frame_id: FrameId) {
+        replacer
+            .record_access(frame_id, frame_id, AccessType::Lookup)
+            .unwrap();
+        replacer.set_evictable(frame_id, true).unwrap();
+    }
+
+    #[test]
+    fn test_evicts_largest_k_distance() {
+        let replacer = LruKReplacer::with_k(7, 2);
+        for frame_id in 1..=6 {
+            replacer
+                .record_access(frame_id, frame_id, AccessType::Lookup)
+                .unwrap();
+        }
+        for frame_id in 1..=5 {
+            replacer.set_evictable(frame_id, true).unwrap();
+        }
+        replacer.set_evictable(6, false).unwrap();
+        assert_eq!(replacer.size(), 5);
+
+        // Frame 1 now has two accesses, the others one.
+        access(&replacer, 1);
+        assert_eq!(replacer.evict(), Some(2));
+        assert_eq!(replacer.evict(), Some(3));
+        assert_eq!(replacer.evict(), Some(4));
+        assert_eq!(replacer.size(), 2);
+
+        access(&replacer, 3);
+        access(&replacer, 4);
+        access(&replacer, 5);
+        access(&replacer, 4);
+        replacer.set_evictable(3, true).unwrap();
+        replacer.set_evictable(4, true).unwrap();
+        assert_eq!(replacer.size(), 4);
+
+        // Frame 3 has a single access, so it goes first.
+        assert_eq!(replacer.evict(), Some(3));
+        assert_eq!(replacer.size(), 3);
+        replacer.set_evictable(6, true).unwrap();
+        assert_eq!(replacer.evict(), Some(6));
+        replacer.set_evictable(1, false).unwrap();
+        assert_eq!(replacer.evict(), Some(5));
+        access(&replacer, 1);
+        access(&replacer, 1);
+        replacer.set_evictable(1, true).unwrap();
+        assert_eq!(replacer.evict(), Some(4));
+        assert_eq!(replacer.evict(), Some(1));
+        assert_eq!(replacer.evict(), None);
+        assert_eq!(replacer.size(), 0);
+    }
+
+    #[test]
+    fn test_fewer_than_k_accesses_use_lru() {
+        let replacer = LruKReplacer::with_k(3, 3);
+        access(&replacer, 0);
+        access(&replacer, 1);
+        access(&replacer, 2);
+        access(&replacer, 0);
+        // None has k accesses; frame 1 was used least recently.
+        assert_eq!(replacer.evict(), Some(1));
+        assert_eq!(replacer.evict(), Some(2));
+        assert_eq!(replacer.evict(), Some(0));
+    }
+
+    #[test]
+    fn test_history_is_limited_to_k() {
+        let replacer = LruKReplacer::with_k(2, 2);
+        for _ in 0..5 {
+            access(&replacer, 0);
+        }
+        access(&replacer, 1);
+        access(&replacer, 1);
+        // Only the last two accesses of frame 0 count, and they are older
+        // than those of frame 1.
+        assert_eq!(replacer.evict(), Some(0));
+    }
+
+    #[test]
+    fn test_default_k() {
+        let replacer = LruKReplacer::new(2);
+        for _ in 0..LRUK_REPLACER_K - 1 {
+            access(&replacer, 0);
+        }
+        access(&replacer, 1);
+        for _ in 0..LRUK_REPLACER_K {
+            access(&replacer, 1);
+        }
+        assert_eq!(replacer.evict(), Some(0));
+    }
+
+    #[test]
+    fn test_remove_and_invalid_frames() {
+        let replacer = LruKReplacer::with_k(3, 2);
+        access(&replacer, 0);
+        replacer.record_access(1, 1, AccessType::Lookup).unwrap();
+        assert!(matches!(replacer.remove(1), Err(Exception::Invalid(_))));
+        replacer.remove(0).unwrap();
+        replacer.remove(2).unwrap();
+        assert_eq!(replacer.size(), 0);
+        assert_eq!(replacer.evict(), None);
+
+        assert!(matches!(
+            replacer.record_access(3, 0, AccessType::Lookup),
+            Err(Exception::OutOfRange(_))
+        ));
+        assert!(matches!(
+            replacer.set_evictable(2, true),
+            Err(Exception::Invalid(_))
+        ));
+        assert!(replacer.record_access(1, 7, AccessType::Lookup).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::buffer::replacer::{AccessType, Replacer, check_frame};
+use crate::common::{
+    config::{FrameId, PageId},
+    exception::Exception,
+};
+
+struct LruNode {
+    page_id: PageId,
+    last_access: u64,
+    is_evictable: bool,
+}
+
+/// Least recently used replacement: evicts the frame whose last access is
+/// the oldest.
+pub struct LruReplacer {
+    replacer_size: usize,
+    latch: Mutex<LruState>,
+}
+
+struct LruState {
+    current_timestamp: u64,
+    curr_size: usize,
+    node_store: HashMap<FrameId, LruNode>,
+}
+
+impl LruReplacer {
+    /// Creates a replacer for the frames `0..num_frames`.
+    pub fn new(num_frames: usize) -> Self {
+        Self {
+            replacer_size: num_frames,
+            latch: Mutex::new(LruState {
+                current_timestamp: 0,
+                curr_size: 0,
+                node_store: HashMap::new(),
+            }),
+        }
+    }
+}
+
+impl Replacer for LruReplacer {
+    fn record_access(
+        &self,
+        frame_id: FrameId,
+        page_id: PageId,
+        _access_type: AccessType,
+    ) -> Result<(), Exception> {
+        check_frame(frame_id, self.replacer_size)?;
+        let mut state = self.latch.lock()?;
+        let timestamp = state.current_timestamp;
+        state.current_timestamp += 1;
+
+        let node = state.node_store.entry(frame_id).or_insert_with(|| LruNode {
+            page_id,
+            last_access: timestamp,
+            is_evictable: false,
+        });
+        if node.page_id != page_id {
+            return Err(Exception::Invalid("Frame already holds another page"));
+        }
+        node.last_access = timestamp;
+        Ok(())
+    }
+
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> Result<(), Exception> {
+        check_frame(frame_id, self.replacer_size)?;
+        let mut state = self.latch.lock()?;
+        let node = state
+            .node_store
+            .get_mut(&frame_id)
+            .ok_or(Exception::Invalid("Frame is not tracked by the replacer"))?;
+        if node.is_evictable == set_evictable {
+            return Ok(());
+        }
+        node.is_evictable = set_evictable;
+        match set_evictable {
+            true => state.curr_size += 1,
+            false => state.curr_size -= 1,
+        }
+        Ok(())
+    }
+
+    fn evict_if(&self, filter: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        let mut state = self.latch.lock().ok()?;
+        let frame_id = state.select(filter)?;
+        state.node_store.remove(&frame_id);
+        state.curr_size -= 1;
+        Some(frame_id)
+    }
+
+    fn victim(&self) -> Option<FrameId> {
+        self.latch.lock().ok()?.select(&|_| true)
+    }
+
+    fn remove(&self, frame_id: FrameId) -> Result<(), Exception> {
+        let mut state = self.latch.lock()?;
+        let Some(node) = state.node_store.get(&frame_id) else {
+            return Ok(());
+        };
+        if !node.is_evictable {
+            return Err(Exception::Invalid("Cannot remove a non-evictable frame"));
+        }
+        state.node_store.remove(&frame_id);
+        state.curr_size -= 1;
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        self.latch.lock().map_or(0, |state| state.curr_size)
+    }
+}
+
+impl LruState {
+    // The least recently used evictable frame that `filter` accepts.
+    fn select(&self, filter: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        self.node_store
+            .iter()
+            .filter(|&(&frame_id, node)| node.is_evictable && filter(frame_id))
+            .min_by_key(|(_, node)| node.last_access)
+            .map(|(&frame_id, _)| frame_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(replacer: &LruReplacer, frame_id: FrameId) {
+        replacer
+            .record_access(frame_id, frame_id, AccessType::Lookup)
+            .unwrap();
+        replacer.set_evictable(frame_id, true).unwrap();
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let replacer = LruReplacer::new(4);
+        for frame_id in 0..4 {
+            access(&replacer, frame_id);
+        }
+        access(&replacer, 0);
+        access(&replacer, 2);
+        replacer.set_evictable(1, false).unwrap();
+        assert_eq!(replacer.evict(), Some(3));
+        assert_eq!(replacer.evict(), Some(0));
+        assert_eq!(replacer.evict(), Some(2));
+        assert_eq!(replacer.evict(), None);
+        replacer.set_evictable(1, true).unwrap();
+        assert_eq!(replacer.evict(), Some(1));
+    }
+}
@@ -1 +1,11 @@
 pub mod arc_replacer;
+pub mod background_flusher;
+pub mod buffer_access_strategy;
+pub mod buffer_pool_manager;
+pub mod clock_replacer;
+pub mod lru_k_replacer;
+pub mod lru_replacer;
+pub mod page_guard;
+pub mod parallel_buffer_pool_manager;
+pub mod replacer;
+pub mod two_queue_replacer;
@@ -0,0 +1,208 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use crate::buffer::{
+    buffer_pool_manager::{BufferPoolManager, BufferPoolOptions},
+    page_guard::{ReadPageGuard, WritePageGuard},
+    replacer::AccessType,
+};
+use crate::common::{config::PageId, exception::Exception};
+use crate::storage::disk::disk_scheduler::DiskScheduler;
+
+/// A buffer pool split into independent instances, each with its own
+/// frames, page table, replacer and latch, so threads working on different
+/// pages rarely contend. Page `page_id` always lives in instance
+/// `page_id % num_instances`, and new pages are handed out by the
+/// instances in turn.
+pub struct ParallelBufferPoolManager {
+    instances: Vec<Arc<BufferPoolManager>>,
+    next_instance: AtomicUsize,
+}
+
+impl ParallelBufferPoolManager {
+    pub fn new(disk_scheduler: Arc<DiskScheduler>, num_instances: usize) -> Self {
+        Self::with_options(disk_scheduler, num_instances, BufferPoolOptions::default())
+    }
+
+    /// Creates `num_instances` pools with `options` each, so the pool has
+    /// `num_instances * options.pool_size` frames in total.
+    pub fn with_options(
+        disk_scheduler: Arc<DiskScheduler>,
+        num_instances: usize,
+        options: BufferPoolOptions,
+    ) -> Self {
+        let num_instances = num_instances.max(1);
+        Self {
+            instances: (0..num_instances)
+                .map(|index| {
+                    Arc::new(BufferPoolManager::for_instance(
+                        disk_scheduler.clone(),
+                        options,
+                        index,
+                        num_instances,
+                    ))
+                })
+                .collect(),
+            next_instance: AtomicUsize::new(0),
+        }
+    }
+
+    /// Allocates a page in the next instance in turn, moving on to the
+    /// following ones while an instance has every frame pinned.
+    pub fn new_page_guarded(&self) -> Result<WritePageGuard<'_>, Exception> {
+        self.allocate(BufferPoolManager::new_page_guarded)
+    }
+
+    pub fn fetch_page_read(
+        &self,
+        page_id: PageId,
+        access_type: AccessType,
+    ) -> Result<ReadPageGuard<'_>, Exception> {
+        self.get_instance(page_id)?
+            .fetch_page_read(page_id, access_type)
+    }
+
+    pub fn fetch_page_write(
+        &self,
+        page_id: PageId,
+        access_type: AccessType,
+    ) -> Result<WritePageGuard<'_>, Exception> {
+        self.get_instance(page_id)?
+            .fetch_page_write(page_id, access_type)
+    }
+
+    pub fn flush_page(&self, page_id: PageId) -> Result<(), Exception> {
+        self.get_instance(page_id)?.flush_page(page_id)
+    }
+
+    /// Flushes every instance, returning the first error after trying all.
+    /// Like `BufferPoolManager::flush_all_pages`, must not be called by a
+    /// thread holding a guard.
+    pub fn flush_all_pages(&self) -> Result<(), Exception> {
+        self.instances
+            .iter()
+            .map(|instance| instance.flush_all_pages())
+            .fold(Ok(()), Result::and)
+    }
+
+    pub fn delete_page(&self, page_id: PageId) -> Result<(), Exception> {
+        self.get_instance(page_id)?.delete_page(page_id)
+    }
+
+    /// Total number of frames over all instances.
+    pub fn get_pool_size(&self) -> usize {
+        self.instances
+            .iter()
+            .map(|instance| instance.get_pool_size())
+            .sum()
+    }
+
+    pub fn get_pin_count(&self, page_id: PageId) -> Option<usize> {
+        self.get_instance(page_id).ok()?.get_pin_count(page_id)
+    }
+
+    pub fn get_num_instances(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// The instances, e.g. to start a `BackgroundFlusher` on.
+    pub fn get_instances(&self) -> &[Arc<BufferPoolManager>] {
+        &self.instances
+    }
+
+    /// The instance responsible for `page_id`.
+    pub fn get_instance(&self, page_id: PageId) -> Result<&BufferPoolManager, Exception> {
+        if page_id < 0 {
+            return Err(Exception::Invalid("Invalid page id"));
+        }
+        Ok(&self.instances[page_id as usize % self.instances.len()])
+    }
+
+    fn allocate<'a, T>(
+        &'a self,
+        allocate: impl Fn(&'a BufferPoolManager) -> Result<T, Exception>,
+    ) -> Result<T, Exception> {
+        let start = self.next_instance.fetch_add(1, Ordering::Relaxed);
+        let mut result = Err(Exception::OutOfMemory(
+            "Every frame of the buffer pool is pinned",
+        ));
+        for i in 0..self.instances.len() {
+            result = allocate(&self.instances[(start + i) % self.instances.len()]);
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::temp_db::TempDb;
+    use crate::storage::disk::disk_manager::DiskManager;
+
+    fn setup(
+        db_name: &str,
+        num_instances: usize,
+        pool_size: usize,
+    ) -> (ParallelBufferPoolManager, TempDb) {
+        let db = TempDb::new(db_name);
+        let disk_manager = Arc::new(DiskManager::new(db.get_path().to_path_buf()).unwrap());
+        let disk_scheduler = Arc::new(DiskScheduler::new(disk_manager));
+        let options = BufferPoolOptions {
+            pool_size,
+            ..Default::default()
+        };
+        (
+            ParallelBufferPoolManager::with_options(disk_scheduler, num_instances, options),
+            db,
+        )
+    }
+
+    #[test]
+    fn test_pages_are_partitioned() {
+        let (bpm, _db) = setup("test_parallel_partition.db", 4, 2);
+        assert_eq!(bpm.get_pool_size(), 8);
+        let mut guards: Vec<_> = (0..8).map(|_| bpm.new_page_guarded().unwrap()).collect();
+        let mut page_ids: Vec<PageId> = guards.iter().map(|guard| guard.get_page_id()).collect();
+        page_ids.sort_unstable();
+        assert_eq!(page_ids, (0..8).collect::<Vec<_>>());
+        for page_id in 0..8 {
+            let instance = bpm.get_instance(page_id).unwrap();
+            assert_eq!(instance.get_pin_count(page_id), Some(1));
+        }
+        // Every instance is full.
+        assert!(matches!(
+            bpm.new_page_guarded(),
+            Err(Exception::OutOfMemory(_))
+        ));
+
+        // Only instance 2 has an unpinned frame, so it takes the next page.
+        guards.retain(|guard| guard.get_page_id() != 6);
+        let guard = bpm.new_page_guarded().unwrap();
+        assert_eq!(guard.get_page_id(), 10);
+        assert_eq!(bpm.get_pin_count(6), None);
+        drop((guard, guards));
+    }
+
+    #[test]
+    fn test_pages_survive_eviction() {
+        let (bpm, _db) = setup("test_parallel_eviction.db", 3, 2);
+        let page_ids: Vec<PageId> = (0..30u8)
+            .map(|i| {
+                let mut guard = bpm.new_page_guarded().unwrap();
+                guard[0] = i;
+                guard.get_page_id()
+            })
+            .collect();
+        for (i, page_id) in page_ids.into_iter().enumerate() {
+            let guard = bpm.fetch_page_read(page_id, AccessType::Lookup).unwrap();
+            assert_eq!(guard[0], i as u8);
+        }
+        bpm.flush_all_pages().unwrap();
+        assert!(bpm.fetch_page_read(-1, AccessType::Lookup).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::buffer::{
+    arc_replacer::ArcReplacer, clock_replacer::ClockReplacer, lru_k_replacer::LruKReplacer,
+    lru_replacer::LruReplacer, two_queue_replacer::TwoQueueReplacer,
+};
+use crate::common::{
+    config::{FrameId, PageId},
+    exception::Exception,
+};
+
+/// Why a page was accessed. Policies may use it to keep one-off accesses,
+/// such as those of a sequential scan, from displacing frequently used
+/// pages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AccessType {
+    #[default]
+    Unknown,
+    Lookup,
+    Scan,
+    Index,
+}
+
+/// A page replacement policy over the frames `0..num_frames` of a buffer
+/// pool. Frames start out non-evictable when their page is first recorded;
+/// only evictable frames count towards `size` and can be evicted.
+pub trait Replacer: Send + Sync {
+    /// Records that `page_id`, held in `frame_id`, was accessed.
+    fn record_access(
+        &self,
+        frame_id: FrameId,
+        page_id: PageId,
+        access_type: AccessType,
+    ) -> Result<(), Exception>;
+
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> Result<(), Exception>;
+
+    /// Picks an evictable frame and stops tracking it. Returns `None` if no
+    /// frame is evictable.
+    fn evict(&self) -> Option<FrameId> {
+        self.evict_if(&|_| true)
+    }
+
+    /// Like `evict`, but only considers the frames `filter` accepts. The
+    /// others keep their place, as if they were not evictable.
+    fn evict_if(&self, filter: &dyn Fn(FrameId) -> bool) -> Option<FrameId>;
+
+    /// The frame `evict` would pick, which stays tracked and evictable.
+    fn victim(&self) -> Option<FrameId>;
+
+    /// Stops tracking `frame_id`, e.g. because its page was deleted. Fails
+    /// for a non-evictable frame and ignores untracked ones.
+    fn remove(&self, frame_id: FrameId) -> Result<(), Exception>;
+
+    /// The number of evictable frames.
+    fn size(&self) -> usize;
+}
+
+/// The replacement policies a buffer pool can be built with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplacerPolicy {
+    #[default]
+    Arc,
+    Lru,
+    /// LRU-K with `LRUK_REPLACER_K`.
+    LruK,
+    Clock,
+    TwoQueue,
+}
+
+impl ReplacerPolicy {
+    pub fn create(self, num_frames: usize) -> Box<dyn Replacer> {
+        match self {
+            Self::Arc => Box::new(ArcReplacer::new(num_frames)),
+            Self::Lru => Box::new(LruReplacer::new(num_frames)),
+            Self::LruK => Box::new(LruKReplacer::new(num_frames)),
+            Self::Clock => Box::new(ClockReplacer::new(num_frames)),
+            Self::TwoQueue => Box::new(TwoQueueReplacer::new(num_frames)),
+        }
+    }
+}
+
+pub(crate) fn check_frame(frame_id: FrameId, num_frames: usize) -> Result<(), Exception> {
+    if frame_id < 0 || frame_id as usize >= num_frames {
+        return Err(Exception::OutOfRange("Frame id is out of range"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICIES: [ReplacerPolicy; 5] = [
+        ReplacerPolicy::Arc,
+        ReplacerPolicy::Lru,
+        ReplacerPolicy::LruK,
+        ReplacerPolicy::Clock,
+        ReplacerPolicy::TwoQueue,
+    ];
+
+    #[test]
+    fn test_policies_share_contract() {
+        for policy in POLICIES {
+            let replacer = policy.create(8);
+            for frame_id in 0..8 {
+                replacer
+                    .record_access(frame_id, frame_id + 100, AccessType::Lookup)
+                    .unwrap();
+            }
+            assert_eq!(replacer.size(), 0, "{policy:?}");
+            assert_eq!(replacer.evict(), None, "{policy:?}");
+
+            for frame_id in 0..8 {
+                replacer.set_evictable(frame_id, frame_id % 2 == 0).unwrap();
+            }
+            assert_eq!(replacer.size(), 4, "{policy:?}");
+            assert!(replacer.remove(1).is_err(), "{policy:?}");
+            replacer.remove(0).unwrap();
+            assert_eq!(replacer.size(), 3, "{policy:?}");
+
+            let mut evicted: Vec<FrameId> = std::iter::from_fn(|| replacer.evict()).collect();
+            evicted.sort_unstable();
+            assert_eq!(evicted, vec![2, 4, 6], "{policy:?}");
+            assert_eq!(replacer.size(), 0, "{policy:?}");
+
+            // Evicted and removed frames can be reused for other pages.
+            replacer.record_access(2, 7, AccessType::Scan).unwrap();
+            replacer.record_access(0, 8, AccessType::Unknown).unwrap();
+            assert!(
+                matches!(
+                    replacer.record_access(8, 9, AccessType::Lookup),
+                    Err(Exception::OutOfRange(_))
+                ),
+                "{policy:?}"
+            );
+            assert!(replacer.set_evictable(4, true).is_err(), "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn test_policies_never_evict_pinned_frames() {
+        for policy in POLICIES {
+            let replacer = policy.create(4);
+            for page_id in 0..200 {
+                let frame_id = match page_id < 4 {
+                    true => page_id,
+                    false => replacer.evict().unwrap(),
+                };
+                replacer
+                    .record_access(frame_id, page_id, AccessType::Lookup)
+                    .unwrap();
+                // Frame 0 keeps page 0 pinned throughout.
+                if frame_id != 0 {
+                    replacer.set_evictable(frame_id, true).unwrap();
+                }
+                if page_id % 3 == 0 && page_id >= 4 {
+                    replacer.record_access(0, 0, AccessType::Lookup).unwrap();
+                }
+            }
+            assert_eq!(replacer.size(), 3, "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn test_policies_peek_and_filter_without_side_effects() {
+        // Two replacers with the same history, so one can be probed and then
+        // compared with the other.
+        let replay = |policy: ReplacerPolicy| {
+            let replacer = policy.create(6);
+            for (frame_id, access_type) in [0, 1, 2, 3, 4, 5, 1, 3, 3, 5, 0]
+                .into_iter()
+                .zip([AccessType::Lookup, AccessType::Scan].into_iter().cycle())
+            {
+                replacer
+                    .record_access(frame_id, frame_id + 100, access_type)
+                    .unwrap();
+                replacer.set_evictable(frame_id, true).unwrap();
+            }
+            replacer
+        };
+        for policy in POLICIES {
+            let probed = replay(policy);
+            let expected: Vec<FrameId> = {
+                let replacer = replay(policy);
+                std::iter::from_fn(|| replacer.evict()).collect()
+            };
+
+            assert_eq!(probed.evict_if(&|_| false), None, "{policy:?}");
+            let mut evicted = Vec::new();
+            while let Some(victim) = probed.victim() {
+                assert_eq!(probed.victim(), Some(victim), "{policy:?}");
+                assert_eq!(probed.size(), 6 - evicted.len(), "{policy:?}");
+                evicted.push(probed.evict().unwrap());
+                assert_eq!(evicted.last(), Some(&victim), "{policy:?}");
+            }
+            assert_eq!(evicted, expected, "{policy:?}");
+
+            let replacer = replay(policy);
+            let odd = replacer.evict_if(&|frame_id| frame_id % 2 == 1).unwrap();
+            assert_eq!(odd % 2, 1, "{policy:?}");
+            assert_eq!(replacer.size(), 5, "{policy:?}");
+            replacer.set_evictable(odd, false).unwrap_err();
+        }
+    }
+}
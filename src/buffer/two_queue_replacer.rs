@@ -0,0 +1,249 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use crate::buffer::replacer::{AccessType, Replacer, check_frame};
+use crate::common::{
+    config::{FrameId, PageId},
+    exception::Exception,
+};
+
+struct TwoQueueNode {
+    page_id: PageId,
+    is_evictable: bool,
+    // Whether the frame is in `am` rather than `a1_in`.
+    is_hot: bool,
+}
+
+/// 2Q replacement. New pages enter the FIFO `a1_in`, and repeated accesses
+/// while there do not promote them. A page evicted from `a1_in` is
+/// remembered in the ghost FIFO `a1_out`; if it is requested again it is
+/// admitted to `am`, an LRU list of hot pages. Pages touched once, such as
+/// those of a scan, therefore never displace the hot set.
+pub struct TwoQueueReplacer {
+    replacer_size: usize,
+    // Frames `a1_in` may keep before it is evicted from first.
+    kin: usize,
+    // Ghost entries `a1_out` remembers.
+    kout: usize,
+    latch: Mutex<TwoQueueState>,
+}
+
+struct TwoQueueState {
+    // Newest first.
+    a1_in: VecDeque<FrameId>,
+    am: VecDeque<FrameId>,
+    a1_out: VecDeque<PageId>,
+    nodes: HashMap<FrameId, TwoQueueNode>,
+    curr_size: usize,
+}
+
+impl TwoQueueReplacer {
+    /// Creates a replacer for the frames `0..num_frames`, with a quarter of
+    /// them for `a1_in` and ghosts for half as many pages as frames.
+    pub fn new(num_frames: usize) -> Self {
+        Self {
+            replacer_size: num_frames,
+            kin: (num_frames / 4).max(1),
+            kout: (num_frames / 2).max(1),
+            latch: Mutex::new(TwoQueueState {
+                a1_in: VecDeque::new(),
+                am: VecDeque::new(),
+                a1_out: VecDeque::new(),
+                nodes: HashMap::new(),
+                curr_size: 0,
+            }),
+        }
+    }
+}
+
+impl Replacer for TwoQueueReplacer {
+    fn record_access(
+        &self,
+        frame_id: FrameId,
+        page_id: PageId,
+        _access_type: AccessType,
+    ) -> Result<(), Exception> {
+        check_frame(frame_id, self.replacer_size)?;
+        let mut state = self.latch.lock()?;
+        if let Some(node) = state.nodes.get(&frame_id) {
+            if node.page_id != page_id {
+                return Err(Exception::Invalid("Frame already holds another page"));
+            }
+            if node.is_hot {
+                remove_frame(&mut state.am, frame_id);
+                state.am.push_front(frame_id);
+            }
+            return Ok(());
+        }
+
+        let is_hot = match state.a1_out.iter().position(|&id| id == page_id) {
+            Some(position) => {
+                state.a1_out.remove(position);
+                state.am.push_front(frame_id);
+                true
+            }
+            None => {
+                state.a1_in.push_front(frame_id);
+                false
+            }
+        };
+        state.nodes.insert(
+            frame_id,
+            TwoQueueNode {
+                page_id,
+                is_evictable: false,
+                is_hot,
+            },
+        );
+        Ok(())
+    }
+
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> Result<(), Exception> {
+        check_frame(frame_id, self.replacer_size)?;
+        let mut state = self.latch.lock()?;
+        let node = state
+            .nodes
+            .get_mut(&frame_id)
+            .ok_or(Exception::Invalid("Frame is not tracked by the replacer"))?;
+        if node.is_evictable == set_evictable {
+            return Ok(());
+        }
+        node.is_evictable = set_evictable;
+        match set_evictable {
+            true => state.curr_size += 1,
+            false => state.curr_size -= 1,
+        }
+        Ok(())
+    }
+
+    /// Evicts from `a1_in` while it holds more than its share, from `am`
+    /// otherwise. Pages evicted from `a1_in` are remembered in `a1_out`.
+    fn evict_if(&self, filter: &dyn Fn(FrameId) -> bool) -> Option<FrameId> {
+        let mut state = self.latch.lock().ok()?;
+        let (cold, position) = state.select(self.kin, filter)?;
+        let frame_id = match cold {
+            true => state.a1_in.remove(position)?,
+            false => state.am.remove(position)?,
+        };
+        let node = state.nodes.remove(&frame_id)?;
+        if !node.is_hot {
+            state.a1_out.push_front(node.page_id);
+            state.a1_out.truncate(self.kout);
+        }
+        state.curr_size -= 1;
+        Some(frame_id)
+    }
+
+    fn victim(&self) -> Option<FrameId> {
+        let state = self.latch.lock().ok()?;
+        let (cold, position) = state.select(self.kin, &|_| true)?;
+        match cold {
+            true => state.a1_in.get(position).copied(),
+            false => state.am.get(position).copied(),
+        }
+    }
+
+    fn remove(&self, frame_id: FrameId) -> Result<(), Exception> {
+        let mut state = self.latch.lock()?;
+        let Some(node) = state.nodes.get(&frame_id) else {
+            return Ok(());
+        };
+        if !node.is_evictable {
+            return Err(Exception::Invalid("Cannot remove a non-evictable frame"));
+        }
+        let is_hot = node.is_hot;
+        match is_hot {
+            true => remove_frame(&mut state.am, frame_id),
+            false => remove_frame(&mut state.a1_in, frame_id),
+        }
+        state.nodes.remove(&frame_id);
+        state.curr_size -= 1;
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        self.latch.lock().map_or(0, |state| state.curr_size)
+    }
+}
+
+impl TwoQueueState {
+    // Finds the oldest evictable frame `filter` accepts: whether it is in
+    // `a1_in` rather than `am`, and its position there.
+    fn select(&self, kin: usize, filter: &dyn Fn(FrameId) -> bool) -> Option<(bool, usize)> {
+        let cold_first = self.a1_in.len() > kin;
+        let select_from = |cold: bool| {
+            let list = match cold {
+                true => &self.a1_in,
+                false => &self.am,
+            };
+            let position = list
+                .iter()
+                .rposition(|&frame_id| self.nodes[&frame_id].is_evictable && filter(frame_id))?;
+            Some((cold, position))
+        };
+        select_from(cold_first).or_else(|| select_from(!cold_first))
+    }
+}
+
+fn remove_frame(list: &mut VecDeque<FrameId>, frame_id: FrameId) {
+    if let Some(position) = list.iter().position(|&id| id == frame_id) {
+        list.remove(position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(replacer: &TwoQueueReplacer, frame_id: FrameId, page_id: PageId) {
+        replacer
+            .record_access(frame_id, page_id, AccessType::Lookup)
+            .unwrap();
+        replacer.set_evictable(frame_id, true).unwrap();
+    }
+
+    #[test]
+    fn test_reaccessed_page_becomes_hot() {
+        let replacer = TwoQueueReplacer::new(4);
+        for frame_id in 0..4 {
+            access(&replacer, frame_id, frame_id + 10);
+        }
+        // a1_in is over its share, so its oldest page goes to a1_out.
+        assert_eq!(replacer.evict(), Some(0));
+        access(&replacer, 0, 10);
+        let state = replacer.latch.lock().unwrap();
+        assert_eq!(state.am, VecDeque::from([0]));
+        assert!(state.a1_out.is_empty());
+    }
+
+    #[test]
+    fn test_scan_does_not_evict_hot_pages() {
+        let replacer = TwoQueueReplacer::new(4);
+        // Make page 10 hot in frame 0.
+        access(&replacer, 0, 10);
+        access(&replacer, 1, 11);
+        assert_eq!(replacer.evict(), Some(0));
+        access(&replacer, 0, 10);
+
+        // Scan pages 100.. through the other three frames.
+        let mut free: VecDeque<FrameId> = VecDeque::from([2, 3]);
+        for page_id in 100..150 {
+            let frame_id = free.pop_front().or_else(|| replacer.evict()).unwrap();
+            assert_ne!(frame_id, 0, "the hot page was evicted by the scan");
+            access(&replacer, frame_id, page_id);
+        }
+    }
+
+    #[test]
+    fn test_repeated_access_in_a1_in_does_not_promote() {
+        let replacer = TwoQueueReplacer::new(8);
+        access(&replacer, 0, 1);
+        access(&replacer, 0, 1);
+        access(&replacer, 1, 2);
+        let state = replacer.latch.lock().unwrap();
+        assert_eq!(state.a1_in, VecDeque::from([1, 0]));
+        assert!(state.am.is_empty());
+    }
+}
@@ -1,32 +1,136 @@
 use std::collections::VecDeque;
 use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::common::exception::Exception;
 
+/// A multi-producer, multi-consumer queue. A channel made with
+/// `with_capacity` holds at most that many elements, and `put` blocks until
+/// there is room. After `close`, every put fails and gets fail once the
+/// remaining elements have been taken.
 pub struct Channel<T> {
-    queue: Mutex<VecDeque<T>>,
-    condvar: Condvar,
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    capacity: usize,
+    closed: bool,
 }
 
 impl<T> Channel<T> {
     pub fn new() -> Self {
+        Self::with_capacity(usize::MAX)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
-            queue: Mutex::new(VecDeque::new()),
-            condvar: Condvar::new(),
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                capacity: capacity.max(1),
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
         }
     }
+
+    /// Queues `element`, waiting for room if the channel is full.
     pub fn put(&self, element: T) -> Result<(), Exception> {
-        let mut queue = self.queue.lock()?;
-        queue.push_back(element);
-        self.condvar.notify_all();
+        let mut state = self.state.lock()?;
+        while !state.closed && state.queue.len() >= state.capacity {
+            state = self.not_full.wait(state)?;
+        }
+        if state.closed {
+            return Err(closed());
+        }
+        state.queue.push_back(element);
+        self.not_empty.notify_one();
         Ok(())
     }
+
+    /// Queues `element` if there is room, without blocking. A full channel
+    /// hands the element back.
+    pub fn try_put(&self, element: T) -> Result<Option<T>, Exception> {
+        let mut state = self.state.lock()?;
+        if state.closed {
+            return Err(closed());
+        }
+        if state.queue.len() >= state.capacity {
+            return Ok(Some(element));
+        }
+        state.queue.push_back(element);
+        self.not_empty.notify_one();
+        Ok(None)
+    }
+
+    /// Waits for the next element.
     pub fn get(&self) -> Result<T, Exception> {
-        let mut queue = self.queue.lock().unwrap();
-        while queue.is_empty() {
-            queue = self.condvar.wait(queue).unwrap();
+        let mut state = self.state.lock()?;
+        loop {
+            if let Some(element) = self.pop(&mut state)? {
+                return Ok(element);
+            }
+            state = self.not_empty.wait(state)?;
+        }
+    }
+
+    /// Returns the next element if one is queued, without blocking.
+    pub fn try_get(&self) -> Result<Option<T>, Exception> {
+        let mut state = self.state.lock()?;
+        self.pop(&mut state)
+    }
+
+    /// Waits at most `timeout` for the next element, returning `None` if
+    /// none arrived in time.
+    pub fn get_timeout(&self, timeout: Duration) -> Result<Option<T>, Exception> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock()?;
+        loop {
+            if let Some(element) = self.pop(&mut state)? {
+                return Ok(Some(element));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            state = self.not_empty.wait_timeout(state, deadline - now)?.0;
+        }
+    }
+
+    /// Closes the channel and wakes every blocked `put` and `get`. Elements
+    /// already queued can still be taken.
+    pub fn close(&self) -> Result<(), Exception> {
+        self.state.lock()?.closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> Result<bool, Exception> {
+        Ok(self.state.lock()?.closed)
+    }
+
+    pub fn len(&self) -> Result<usize, Exception> {
+        Ok(self.state.lock()?.queue.len())
+    }
+
+    pub fn is_empty(&self) -> Result<bool, Exception> {
+        Ok(self.len()? == 0)
+    }
+
+    // Takes the front element, or fails if the channel is closed and empty.
+    fn pop(&self, state: &mut State<T>) -> Result<Option<T>, Exception> {
+        match state.queue.pop_front() {
+            Some(element) => {
+                self.not_full.notify_one();
+                Ok(Some(element))
+            }
+            None if state.closed => Err(closed()),
+            None => Ok(None),
         }
-        Ok(queue.pop_front().unwrap())
     }
 }
 
@@ -35,3 +139,103 @@ impl<T> Default for Channel<T> {
         Self::new()
     }
 }
+
+fn closed() -> Exception {
+    Exception::Execution("Channel is closed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::exception::ExceptionType;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn test_fifo_order() {
+        let channel = Channel::new();
+        for i in 0..10 {
+            channel.put(i).unwrap();
+        }
+        assert_eq!(channel.len().unwrap(), 10);
+        let received: Vec<i32> = (0..10).map(|_| channel.get().unwrap()).collect();
+        assert_eq!(received, (0..10).collect::<Vec<_>>());
+        assert!(channel.try_get().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_bounded_put_blocks_until_room() {
+        let channel = Arc::new(Channel::with_capacity(2));
+        channel.put(1).unwrap();
+        assert_eq!(channel.try_put(2).unwrap(), None);
+        assert_eq!(channel.try_put(3).unwrap(), Some(3));
+
+        let producer = {
+            let channel = channel.clone();
+            thread::spawn(move || channel.put(3))
+        };
+        assert_eq!(channel.get().unwrap(), 1);
+        producer.join().unwrap().unwrap();
+        assert_eq!(channel.len().unwrap(), 2);
+        assert_eq!(channel.get().unwrap(), 2);
+        assert_eq!(channel.get().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_get_timeout() {
+        let channel = Channel::with_capacity(1);
+        let timeout = Duration::from_millis(20);
+        let start = Instant::now();
+        assert_eq!(channel.get_timeout(timeout).unwrap(), None);
+        assert!(start.elapsed() >= timeout);
+        channel.put(5).unwrap();
+        assert_eq!(channel.get_timeout(timeout).unwrap(), Some(5));
+    }
+
+    #[test]
+    fn test_close_wakes_waiters() {
+        let channel = Arc::new(Channel::<i32>::with_capacity(1));
+        let consumer = {
+            let channel = channel.clone();
+            thread::spawn(move || channel.get())
+        };
+        thread::sleep(Duration::from_millis(20));
+        channel.close().unwrap();
+        let error = consumer.join().unwrap().unwrap_err();
+        assert_eq!(error.get_type(), ExceptionType::Execution);
+
+        assert!(channel.is_closed().unwrap());
+        assert!(channel.put(1).is_err());
+        assert!(channel.try_put(1).is_err());
+        assert!(channel.get_timeout(Duration::from_millis(1)).is_err());
+    }
+
+    #[test]
+    fn test_poisoned_lock_is_an_error() {
+        let channel = Arc::new(Channel::<i32>::new());
+        let poisoner = {
+            let channel = channel.clone();
+            thread::spawn(move || {
+                let _state = channel.state.lock().unwrap();
+                panic!("poison the channel");
+            })
+        };
+        assert!(poisoner.join().is_err());
+        assert!(channel.get().is_err());
+        assert!(channel.put(1).is_err());
+    }
+
+    #[test]
+    fn test_close_keeps_queued_elements() {
+        let channel = Arc::new(Channel::with_capacity(1));
+        channel.put(1).unwrap();
+        let producer = {
+            let channel = channel.clone();
+            thread::spawn(move || channel.put(2))
+        };
+        thread::sleep(Duration::from_millis(20));
+        channel.close().unwrap();
+        assert!(producer.join().unwrap().is_err());
+        assert_eq!(channel.get().unwrap(), 1);
+        assert!(channel.try_get().is_err());
+    }
+}
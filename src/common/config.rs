@@ -20,6 +20,9 @@ pub const INVALID_LSN_ID: LsnId = -1;
 pub const DOCKBASE_PAGE_SIZE: usize = 8192;
 pub const BUFFER_POOL_SIZE: usize = 128;
 pub const DEFAULT_DB_IO_SIZE: usize = 16;
+pub const BULK_READ_RING_SIZE: usize = 16;
+pub const BULK_WRITE_RING_SIZE: usize = 32;
+pub const DOUBLE_WRITE_BUFFER_SIZE: usize = 32;
 pub const LOG_BUFFER_SIZE: usize = (BUFFER_POOL_SIZE + 1 ) * DOCKBASE_PAGE_SIZE;
 pub const BUCKET_SIZE: usize = 50;
 pub const LRUK_REPLACER_K: usize = 10;
@@ -12,6 +12,7 @@ pub enum ExceptionType {
     NotImplemented = 11,
     Execution = 12,
     IO = 13,
+    Corruption = 14,
 }
 
 #[macro_export]
@@ -23,7 +24,7 @@ macro_rules! throw {
 
 macro_rules! define_exceptions {
   ($($variant:ident => ($enum_val:path, $string:expr)),* $(,)?) => {
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub enum Exception {
       $($variant(&'static str),)*
     }
@@ -78,6 +79,7 @@ define_exceptions! {
     NotImplemented => (ExceptionType::NotImplemented, "Not implemented"),
     Execution => (ExceptionType::Execution, "Execution"),
     IO => (ExceptionType::IO, "IO Error"),
+    Corruption => (ExceptionType::Corruption, "Corruption"),
 }
 
 impl std::error::Error for Exception {}
@@ -2,3 +2,5 @@ pub mod config;
 pub mod channel;
 pub mod logger;
 pub mod exception;
+pub mod checksum;
+pub mod temp_db;
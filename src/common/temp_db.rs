@@ -0,0 +1,68 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The files of a scratch database for tests and benchmarks: the page
+/// file, the double-write file beside it and the log file `DiskManager`
+/// keeps in the working directory. They are removed when the `TempDb` is
+/// created, in case an earlier run left them behind, and when it is
+/// dropped.
+pub struct TempDb {
+    path: PathBuf,
+}
+
+impl TempDb {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let temp_db = Self { path: path.into() };
+        temp_db.remove_files();
+        temp_db
+    }
+
+    /// Path of the page file.
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_log_path(&self) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default();
+        PathBuf::from(stem).with_extension("log")
+    }
+
+    fn remove_files(&self) {
+        let _ = fs::remove_file(&self.path);
+        let _ = fs::remove_file(self.path.with_extension("dwb"));
+        let _ = fs::remove_file(self.get_log_path());
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        self.remove_files();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::disk::disk_manager::DiskManager;
+
+    #[test]
+    fn test_removes_database_files() {
+        let db = TempDb::new("test_temp_db.db");
+        let disk_manager = DiskManager::new(db.get_path().into()).unwrap();
+        assert_eq!(disk_manager.get_log_file_name(), db.get_log_path());
+        drop(disk_manager);
+        let paths = [
+            db.get_path().to_path_buf(),
+            db.get_path().with_extension("dwb"),
+            db.get_log_path(),
+        ];
+        assert!(paths.iter().all(|path| path.exists()));
+
+        // Files left behind are cleared when the database is set up again.
+        let db = TempDb::new(db.get_path());
+        assert!(paths.iter().all(|path| !path.exists()));
+        drop(db);
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use crate::common::{config::PageId, exception::Exception};
+use crate::storage::disk::{
+    disk_manager::DiskManager,
+    disk_scheduler::{DiskRequest, RequestType, WorkerTask},
+    worker_queue::WorkerQueue,
+};
+
+// Requests taken off the queue at once.
+const MAX_BATCH: usize = 256;
+
+/// Worker loop of a coalescing scheduler. Whatever is waiting on the queue
+/// is taken as one batch, up to the next fence, and handed to `run_batch`.
+pub(crate) fn run_worker(
+    disk_manager: Arc<DiskManager>,
+    queue: Arc<WorkerQueue>,
+    num_collapsed_writes: Arc<AtomicUsize>,
+) {
+    let mut closed = false;
+    while !closed {
+        let mut requests = Vec::new();
+        let mut fence = None;
+        let mut next = queue.get().map(Some);
+        loop {
+            match next {
+                Ok(Some(Some(WorkerTask::Request(request)))) => requests.push(request),
+                Ok(Some(Some(WorkerTask::Fence(task_fence)))) => {
+                    fence = Some(task_fence);
+                    break;
+                }
+                Ok(None) => break,
+                Ok(Some(None)) | Err(_) => {
+                    closed = true;
+                    break;
+                }
+            }
+            if requests.len() >= MAX_BATCH {
+                break;
+            }
+            next = queue.try_get();
+        }
+        run_batch(&disk_manager, requests, &num_collapsed_writes);
+        if let Some(fence) = fence {
+            fence.arrive(&disk_manager);
+        }
+    }
+}
+
+// A request to run, and the earlier writes of its page it made redundant.
+struct Planned {
+    request: DiskRequest,
+    superseded: Vec<DiskRequest>,
+}
+
+/// Runs `requests` with the same outcome as running them one by one in
+/// queue order. A write followed by another write of the same page, with
+/// no read or delete in between, is never run and completes with the later
+/// write's result. Requests for different pages are independent, so the
+/// rest run in rounds that take the next request of every page: the writes
+/// and reads of a round go to the disk manager together, which issues them
+/// in offset order and merges adjacent pages.
+fn run_batch(
+    disk_manager: &DiskManager,
+    requests: Vec<DiskRequest>,
+    num_collapsed_writes: &AtomicUsize,
+) {
+    let mut pages: Vec<VecDeque<Planned>> = Vec::new();
+    let mut page_index: HashMap<PageId, usize> = HashMap::new();
+    for request in requests {
+        let index = *page_index.entry(request.page_id).or_insert_with(|| {
+            pages.push(VecDeque::new());
+            pages.len() - 1
+        });
+        let queue = &mut pages[index];
+        let mut planned = Planned {
+            request,
+            superseded: Vec::new(),
+        };
+        if planned.request.request_type == RequestType::Write
+            && let Some(last) = queue.back()
+            && last.request.request_type == RequestType::Write
+        {
+            let last = queue.pop_back().unwrap();
+            planned.superseded = last.superseded;
+            planned.superseded.push(last.request);
+            num_collapsed_writes.fetch_add(1, Ordering::Relaxed);
+        }
+        queue.push_back(planned);
+    }
+
+    loop {
+        let round: Vec<Planned> = pages.iter_mut().filter_map(VecDeque::pop_front).collect();
+        if round.is_empty() {
+            break;
+        }
+        let (mut writes, mut reads, mut others) = (Vec::new(), Vec::new(), Vec::new());
+        for planned in round {
+            match planned.request.request_type {
+                RequestType::Write => writes.push(planned),
+                RequestType::Read => reads.push(planned),
+                _ => others.push(planned),
+            }
+        }
+
+        let pages_to_write: Vec<(PageId, &[u8])> = writes
+            .iter()
+            .map(|planned| (planned.request.page_id, &planned.request.data[..]))
+            .collect();
+        let results = disk_manager.write_pages(&pages_to_write);
+        for (planned, result) in writes.into_iter().zip(results) {
+            complete(planned, result);
+        }
+
+        let mut pages_to_read: Vec<(PageId, &mut [u8])> = reads
+            .iter_mut()
+            .map(|planned| (planned.request.page_id, &mut planned.request.data[..]))
+            .collect();
+        let results = disk_manager.read_pages(&mut pages_to_read);
+        for (planned, result) in reads.into_iter().zip(results) {
+            complete(planned, result);
+        }
+
+        for mut planned in others {
+            let result = planned.request.execute(disk_manager);
+            complete(planned, result);
+        }
+    }
+}
+
+fn complete(planned: Planned, result: Result<(), Exception>) {
+    for request in planned.superseded {
+        request.complete(result.clone());
+    }
+    planned.request.complete(result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::temp_db::TempDb;
+    use crate::storage::disk::{
+        disk_scheduler::{DiskCompletion, RequestPriority},
+        page_buffer::PageBuffer,
+    };
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_run_batch_collapses_writes() {
+        let db = TempDb::new("test_batch_worker.db");
+        let disk_manager = DiskManager::new(db.get_path().into()).unwrap();
+        let (tx, rx) = mpsc::channel::<DiskCompletion>();
+        let request = |request_type, page_id: PageId, fill: u8| DiskRequest {
+            request_type,
+            data: PageBuffer::from_slice(&[fill; 16]),
+            page_id,
+            priority: RequestPriority::Foreground,
+            callback: tx.clone().into(),
+        };
+
+        // Pages 0..20 are written twice and read; page 5 is then deleted.
+        let mut requests = Vec::new();
+        for fill in 1..=2 {
+            requests.extend((0..20).map(|page_id| request(RequestType::Write, page_id, fill)));
+        }
+        requests.extend((0..20).map(|page_id| request(RequestType::Read, page_id, 0)));
+        requests.push(request(RequestType::Delete, 5, 0));
+        requests.push(request(RequestType::Read, 5, 0));
+        let collapsed = AtomicUsize::new(0);
+        run_batch(&disk_manager, requests, &collapsed);
+
+        assert_eq!(collapsed.load(Ordering::Relaxed), 20);
+        assert_eq!(disk_manager.get_num_writes().unwrap(), 20);
+        assert!(disk_manager.get_num_saved_syscalls().unwrap() > 0);
+        let completions: Vec<DiskCompletion> = rx.try_iter().collect();
+        assert_eq!(completions.len(), 62);
+        for completion in &completions {
+            match (completion.request_type, completion.page_id) {
+                (RequestType::Read, 5) if completion.data[0] == 0 => {
+                    assert!(matches!(completion.result, Err(Exception::Invalid(_))));
+                }
+                (RequestType::Read, _) => {
+                    assert!(completion.result.is_ok());
+                    assert_eq!(&completion.data[..16], &[2; 16]);
+                }
+                _ => assert!(completion.result.is_ok()),
+            }
+        }
+    }
+}
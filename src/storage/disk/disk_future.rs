@@ -0,0 +1,263 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use crate::common::exception::Exception;
+use crate::storage::disk::page_buffer::PageBuffer;
+
+/// The result of a request scheduled with `DiskScheduler::schedule_async`:
+/// the request's page buffer, holding the page for a read, or the error
+/// that failed it together with the buffer. It can be awaited from any
+/// executor or waited on directly with `wait`.
+pub struct DiskFuture {
+    shared: Arc<Shared>,
+}
+
+/// The worker's half of a `DiskFuture`. Dropping it unfulfilled, e.g.
+/// because the scheduler shut down first, fails the future instead of
+/// leaving it pending forever.
+pub struct DiskPromise {
+    shared: Arc<Shared>,
+}
+
+/// A failed disk request. The caller's buffer comes back with the error, so
+/// it can be inspected or reused; it is empty if the request was dropped
+/// before it ran.
+#[derive(Debug)]
+pub struct DiskError {
+    pub error: Exception,
+    pub data: PageBuffer,
+}
+
+impl From<DiskError> for Exception {
+    fn from(error: DiskError) -> Self {
+        error.error
+    }
+}
+
+impl DiskError {
+    // An error for a request whose buffer is gone.
+    fn without_buffer(error: Exception) -> Self {
+        Self {
+            error,
+            data: PageBuffer::empty(),
+        }
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    outcome: Option<Result<PageBuffer, DiskError>>,
+    fulfilled: bool,
+    waker: Option<Waker>,
+}
+
+pub(crate) fn promise() -> (DiskPromise, DiskFuture) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State::default()),
+        ready: Condvar::new(),
+    });
+    (
+        DiskPromise {
+            shared: shared.clone(),
+        },
+        DiskFuture { shared },
+    )
+}
+
+/// Waits for every future and returns their buffers in order, or the
+/// first error. All futures are waited for even after one fails, so no
+/// request of the batch is still running when this returns.
+pub fn wait_all(
+    futures: impl IntoIterator<Item = DiskFuture>,
+) -> Result<Vec<PageBuffer>, Exception> {
+    let mut buffers = Vec::new();
+    let mut first_error = None;
+    for future in futures {
+        match future.wait() {
+            Ok(buffer) => buffers.push(buffer),
+            Err(error) => {
+                first_error.get_or_insert(error.error);
+            }
+        }
+    }
+    match first_error {
+        Some(error) => Err(error),
+        None => Ok(buffers),
+    }
+}
+
+impl DiskFuture {
+    /// Blocks the calling thread until the request completes.
+    pub fn wait(self) -> Result<PageBuffer, DiskError> {
+        let poisoned = |error| DiskError::without_buffer(Exception::from(error));
+        let mut state = self.shared.state.lock().map_err(poisoned)?;
+        loop {
+            if let Some(outcome) = state.outcome.take() {
+                return outcome;
+            }
+            state = self.shared.ready.wait(state).map_err(poisoned)?;
+        }
+    }
+
+    /// Returns the outcome if the request has completed, without blocking.
+    /// Once it has returned it, the future is spent.
+    pub fn try_wait(&mut self) -> Option<Result<PageBuffer, DiskError>> {
+        match self.shared.state.lock() {
+            Ok(mut state) => state.outcome.take(),
+            Err(error) => Some(Err(DiskError::without_buffer(error.into()))),
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.shared
+            .state
+            .lock()
+            .map(|state| state.outcome.is_some())
+            .unwrap_or(true)
+    }
+}
+
+impl Future for DiskFuture {
+    type Output = Result<PageBuffer, DiskError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = match self.shared.state.lock() {
+            Ok(state) => state,
+            Err(error) => return Poll::Ready(Err(DiskError::without_buffer(error.into()))),
+        };
+        match state.outcome.take() {
+            Some(outcome) => Poll::Ready(outcome),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl DiskPromise {
+    pub(crate) fn fulfill(self, outcome: Result<PageBuffer, DiskError>) {
+        self.set(outcome);
+    }
+
+    fn set(&self, outcome: Result<PageBuffer, DiskError>) {
+        let Ok(mut state) = self.shared.state.lock() else {
+            return;
+        };
+        if state.fulfilled {
+            return;
+        }
+        state.fulfilled = true;
+        state.outcome = Some(outcome);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        self.shared.ready.notify_all();
+    }
+}
+
+impl Drop for DiskPromise {
+    fn drop(&mut self) {
+        self.set(Err(DiskError::without_buffer(Exception::Execution(
+            "Disk request was dropped before completing",
+        ))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::exception::ExceptionType;
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        task::Wake,
+        thread,
+    };
+
+    struct FlagWaker(AtomicBool);
+
+    impl Wake for FlagWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_wait_returns_buffer() {
+        let (promise, future) = promise();
+        let handle = thread::spawn(move || {
+            let mut page = PageBuffer::new();
+            page[0] = 7;
+            promise.fulfill(Ok(page));
+        });
+        assert_eq!(future.wait().unwrap()[0], 7);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_try_wait_does_not_block() {
+        let (promise, mut future) = promise();
+        assert!(future.try_wait().is_none());
+        let mut page = PageBuffer::new();
+        page[0] = 7;
+        promise.fulfill(Ok(page));
+        assert_eq!(future.try_wait().unwrap().unwrap()[0], 7);
+    }
+
+    #[test]
+    fn test_poll_wakes_on_fulfill() {
+        let (promise, mut future) = promise();
+        let flag = Arc::new(FlagWaker(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+        assert!(!future.is_ready());
+        promise.fulfill(Ok(PageBuffer::new()));
+        assert!(flag.0.load(Ordering::SeqCst));
+        assert!(future.is_ready());
+        assert!(matches!(
+            Pin::new(&mut future).poll(&mut cx),
+            Poll::Ready(Ok(_))
+        ));
+    }
+
+    #[test]
+    fn test_dropped_promise_fails_future() {
+        let (promise, future) = promise();
+        drop(promise);
+        let error = future.wait().unwrap_err();
+        assert_eq!(error.error.get_type(), ExceptionType::Execution);
+        assert!(error.data.is_empty());
+    }
+
+    #[test]
+    fn test_wait_all_reports_first_error() {
+        let (ok_promise, ok_future) = promise();
+        let (failed_promise, failed_future) = promise();
+        let (late_promise, late_future) = promise();
+        ok_promise.fulfill(Ok(PageBuffer::new()));
+        failed_promise.fulfill(Err(DiskError::without_buffer(Exception::Invalid("first"))));
+        late_promise.fulfill(Err(DiskError::without_buffer(Exception::IO("second"))));
+        let result = wait_all([ok_future, failed_future, late_future]);
+        assert!(matches!(result, Err(Exception::Invalid("first"))));
+
+        let futures: Vec<DiskFuture> = (0..3)
+            .map(|_| {
+                let (promise, future) = promise();
+                promise.fulfill(Ok(PageBuffer::new()));
+                future
+            })
+            .collect();
+        assert_eq!(wait_all(futures).unwrap().len(), 3);
+    }
+}
@@ -1,74 +1,363 @@
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
 
-use crate::common::channel::Channel;
-use crate::common::config::{DOCKBASE_PAGE_SIZE, PageId};
+use crate::common::config::PageId;
 use crate::common::exception::Exception;
-use crate::storage::disk::disk_manager::DiskManager;
+use crate::storage::disk::{
+    batch_worker,
+    disk_future::{self, DiskError, DiskFuture, DiskPromise},
+    disk_manager::DiskManager,
+    page_buffer::PageBuffer,
+    worker_queue::WorkerQueue,
+};
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use crate::{log_warn, storage::disk::uring_worker};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RequestType {
     Read,
     Write,
+    Delete,
+    /// Durably flushes every page written by requests scheduled before it.
+    Sync,
+    /// Completes once every request scheduled before it has finished.
+    Barrier,
 }
+
+/// How urgently a request is served. Workers serve higher priorities first,
+/// but a priority that keeps being passed over is served eventually, so
+/// background work is delayed rather than starved. `Sync` and `Barrier`
+/// ignore it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RequestPriority {
+    /// A query is waiting for the request, e.g. on a page miss.
+    #[default]
+    Foreground,
+    /// Pages read ahead of a scan.
+    Prefetch,
+    /// Write-back of dirty pages and other maintenance.
+    Background,
+}
+
+/// A disk operation. The scheduler owns `data` while the request is in
+/// flight and hands it back in the request's `DiskCompletion`; for a read it
+/// then holds the page. Requests other than reads and writes carry
+/// `PageBuffer::empty()`, and `Sync` and `Barrier` ignore `page_id`.
 pub struct DiskRequest {
     pub request_type: RequestType,
-    pub data: *mut u8,
+    pub data: PageBuffer,
     pub page_id: PageId,
-    pub callback: Sender<bool>,
+    pub priority: RequestPriority,
+    pub callback: DiskCallback,
+}
+
+/// Where a finished request is reported.
+pub enum DiskCallback {
+    Channel(Sender<DiskCompletion>),
+    Promise(DiskPromise),
+}
+
+/// Reports a finished request: which page and operation it was, its buffer,
+/// and the error from the disk manager if it failed.
+pub struct DiskCompletion {
+    pub page_id: PageId,
+    pub request_type: RequestType,
+    pub data: PageBuffer,
+    pub result: Result<(), Exception>,
+}
+/// The I/O engine behind a `DiskScheduler`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedulerBackend {
+    /// Requests run one at a time with blocking calls.
+    Blocking,
+    /// Many requests are in flight at once on an io_uring. Used when the
+    /// `io-uring` feature is enabled and the kernel supports it.
+    IoUring,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DiskSchedulerOptions {
+    /// Number of worker threads. Each page id is always served by the same
+    /// worker, so requests for one page still run in submission order.
+    pub num_workers: usize,
+    /// Run the requests waiting on a worker as one batch: a write that is
+    /// overwritten before anything reads the page is skipped, and the rest
+    /// are issued in page offset order with adjacent pages sharing one
+    /// vectored call. Uses the blocking backend.
+    pub coalesce: bool,
+    /// Requests each worker's queue holds before `schedule` blocks until
+    /// the worker catches up.
+    pub queue_capacity: usize,
+}
+
+impl Default for DiskSchedulerOptions {
+    fn default() -> Self {
+        Self {
+            num_workers: 1,
+            coalesce: false,
+            queue_capacity: 4096,
+        }
+    }
+}
+
+/// What a worker takes off its `WorkerQueue`; `None` stops the worker.
+pub(crate) enum WorkerTask {
+    Request(DiskRequest),
+    Fence(Arc<Fence>),
+}
+
+/// A `Sync` or `Barrier` request, queued on every worker. A worker arrives
+/// at the fence once everything queued on it before the fence has
+/// finished, and the last worker to arrive executes the request.
+pub(crate) struct Fence {
+    remaining: AtomicUsize,
+    request: Mutex<Option<DiskRequest>>,
 }
+
 pub struct DiskScheduler {
     disk_manager: Arc<DiskManager>,
-    request_queue: Arc<Channel<Option<DiskRequest>>>,
-    background_thread: Option<JoinHandle<()>>,
+    request_queues: Vec<Arc<WorkerQueue>>,
+    backend: SchedulerBackend,
+    num_collapsed_writes: Arc<AtomicUsize>,
+    background_threads: Vec<JoinHandle<()>>,
 }
 
 impl DiskScheduler {
     pub fn new(disk_manager: Arc<DiskManager>) -> Self {
-        let request_queue = Arc::new(Channel::<Option<DiskRequest>>::new());
+        Self::with_options(disk_manager, DiskSchedulerOptions::default())
+    }
 
-        let worker_disk_manager = disk_manager.clone();
-        let worker_queue = request_queue.clone();
+    pub fn with_options(disk_manager: Arc<DiskManager>, options: DiskSchedulerOptions) -> Self {
+        let request_queues: Vec<_> = (0..options.num_workers.max(1))
+            .map(|_| Arc::new(WorkerQueue::with_capacity(options.queue_capacity)))
+            .collect();
 
-        let background_thread = thread::spawn(move || {
-            Self::start_worker_thread(worker_disk_manager, worker_queue);
-        });
+        let num_collapsed_writes = Arc::new(AtomicUsize::new(0));
+        let (backend, background_threads) = match options.coalesce {
+            true => {
+                Self::spawn_batch_workers(&disk_manager, &request_queues, &num_collapsed_writes)
+            }
+            false => Self::spawn_workers(&disk_manager, &request_queues),
+        };
         Self {
             disk_manager,
-            request_queue,
-            background_thread: Some(background_thread),
+            request_queues,
+            backend,
+            num_collapsed_writes,
+            background_threads,
         }
     }
+
+    /// Queues `requests` on their workers, waiting for room whenever a
+    /// worker's queue is full.
     pub fn schedule(&self, mut requests: Vec<DiskRequest>) -> Result<(), Exception> {
         for request in requests.drain(..) {
-            self.request_queue.put(Some(request))?;
+            match request.request_type {
+                RequestType::Sync | RequestType::Barrier => {
+                    let fence = Arc::new(Fence::new(request, self.request_queues.len()));
+                    for queue in &self.request_queues {
+                        queue.put(WorkerTask::Fence(fence.clone()))?;
+                    }
+                }
+                _ => {
+                    let queue = &self.request_queues[self.worker_index(request.page_id)];
+                    queue.put(WorkerTask::Request(request))?;
+                }
+            }
         }
         Ok(())
     }
-    fn start_worker_thread(
-        disk_manager: Arc<DiskManager>,
-        queue: Arc<Channel<Option<DiskRequest>>>,
-    ) {
-        while let Ok(Some(request)) = queue.get() {
-            let page_data =
-                unsafe { std::slice::from_raw_parts_mut(request.data, DOCKBASE_PAGE_SIZE) };
-            let result = match request.request_type {
-                RequestType::Read => disk_manager.read_page(request.page_id, page_data),
-                RequestType::Write => disk_manager.write_page(request.page_id, page_data),
-            };
-            let _ = request.callback.send(result.is_ok());
+
+    /// Schedules a single request and returns a future for its buffer, so
+    /// callers can issue many requests and then await them together with
+    /// `disk_future::wait_all`.
+    pub fn schedule_async(
+        &self,
+        request_type: RequestType,
+        page_id: PageId,
+        data: PageBuffer,
+    ) -> DiskFuture {
+        self.schedule_async_with_priority(request_type, page_id, data, RequestPriority::default())
+    }
+
+    pub fn schedule_async_with_priority(
+        &self,
+        request_type: RequestType,
+        page_id: PageId,
+        data: PageBuffer,
+        priority: RequestPriority,
+    ) -> DiskFuture {
+        let (promise, future) = disk_future::promise();
+        let request = DiskRequest {
+            request_type,
+            data,
+            page_id,
+            priority,
+            callback: DiskCallback::Promise(promise),
+        };
+        // A request that cannot be queued is dropped, which fails its future.
+        let _ = self.schedule(vec![request]);
+        future
+    }
+
+    pub fn get_disk_manager(&self) -> &Arc<DiskManager> {
+        &self.disk_manager
+    }
+
+    pub fn get_backend(&self) -> SchedulerBackend {
+        self.backend
+    }
+
+    pub fn get_num_workers(&self) -> usize {
+        self.request_queues.len()
+    }
+
+    /// Writes a coalescing scheduler skipped because a later write of the
+    /// same page replaced them.
+    pub fn get_num_collapsed_writes(&self) -> usize {
+        self.num_collapsed_writes.load(Ordering::Relaxed)
+    }
+
+    // Pages are spread over the workers by id. Consecutive ids land on
+    // different workers, so sequential scans use all of them.
+    fn worker_index(&self, page_id: PageId) -> usize {
+        page_id.rem_euclid(self.request_queues.len() as PageId) as usize
+    }
+
+    fn spawn_workers(
+        disk_manager: &Arc<DiskManager>,
+        queues: &[Arc<WorkerQueue>],
+    ) -> (SchedulerBackend, Vec<JoinHandle<()>>) {
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        match queues
+            .iter()
+            .map(|_| uring_worker::open_ring())
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(rings) => {
+                let handles = rings
+                    .into_iter()
+                    .zip(queues)
+                    .map(|(ring, queue)| {
+                        let (disk_manager, queue) = (disk_manager.clone(), queue.clone());
+                        thread::spawn(move || uring_worker::run_worker(ring, disk_manager, queue))
+                    })
+                    .collect();
+                return (SchedulerBackend::IoUring, handles);
+            }
+            Err(error) => log_warn!("io_uring is unavailable, using blocking I/O: {error}"),
+        }
+        let handles = queues
+            .iter()
+            .map(|queue| {
+                let (disk_manager, queue) = (disk_manager.clone(), queue.clone());
+                thread::spawn(move || Self::start_worker_thread(disk_manager, queue))
+            })
+            .collect();
+        (SchedulerBackend::Blocking, handles)
+    }
+
+    fn spawn_batch_workers(
+        disk_manager: &Arc<DiskManager>,
+        queues: &[Arc<WorkerQueue>],
+        num_collapsed_writes: &Arc<AtomicUsize>,
+    ) -> (SchedulerBackend, Vec<JoinHandle<()>>) {
+        let handles = queues
+            .iter()
+            .map(|queue| {
+                let (disk_manager, queue) = (disk_manager.clone(), queue.clone());
+                let num_collapsed_writes = num_collapsed_writes.clone();
+                thread::spawn(move || {
+                    batch_worker::run_worker(disk_manager, queue, num_collapsed_writes)
+                })
+            })
+            .collect();
+        (SchedulerBackend::Blocking, handles)
+    }
+
+    fn start_worker_thread(disk_manager: Arc<DiskManager>, queue: Arc<WorkerQueue>) {
+        while let Ok(Some(task)) = queue.get() {
+            match task {
+                WorkerTask::Request(mut request) => {
+                    let result = request.execute(&disk_manager);
+                    request.complete(result);
+                }
+                WorkerTask::Fence(fence) => fence.arrive(&disk_manager),
+            }
         }
     }
 }
-unsafe impl Send for DiskRequest {}
-unsafe impl Sync for DiskRequest {}
+
+impl DiskRequest {
+    /// Runs the request with blocking calls.
+    pub(crate) fn execute(&mut self, disk_manager: &DiskManager) -> Result<(), Exception> {
+        match self.request_type {
+            RequestType::Read => disk_manager.read_page(self.page_id, &mut self.data),
+            RequestType::Write => disk_manager.write_page(self.page_id, &self.data),
+            RequestType::Delete => disk_manager.delete_page(self.page_id),
+            RequestType::Sync => disk_manager.sync_pages(),
+            RequestType::Barrier => Ok(()),
+        }
+    }
+
+    /// Returns the buffer to the caller. A caller that is no longer waiting
+    /// has dropped its receiver, and the buffer is simply freed.
+    pub(crate) fn complete(self, result: Result<(), Exception>) {
+        match self.callback {
+            DiskCallback::Channel(sender) => {
+                let _ = sender.send(DiskCompletion {
+                    page_id: self.page_id,
+                    request_type: self.request_type,
+                    data: self.data,
+                    result,
+                });
+            }
+            DiskCallback::Promise(promise) => promise.fulfill(match result {
+                Ok(()) => Ok(self.data),
+                Err(error) => Err(DiskError {
+                    error,
+                    data: self.data,
+                }),
+            }),
+        }
+    }
+}
+
+impl Fence {
+    pub(crate) fn new(request: DiskRequest, num_workers: usize) -> Self {
+        Self {
+            remaining: AtomicUsize::new(num_workers),
+            request: Mutex::new(Some(request)),
+        }
+    }
+
+    pub(crate) fn arrive(&self, disk_manager: &DiskManager) {
+        if self.remaining.fetch_sub(1, Ordering::AcqRel) != 1 {
+            return;
+        }
+        if let Some(mut request) = self.request.lock().ok().and_then(|mut slot| slot.take()) {
+            let result = request.execute(disk_manager);
+            request.complete(result);
+        }
+    }
+}
+
+impl From<Sender<DiskCompletion>> for DiskCallback {
+    fn from(sender: Sender<DiskCompletion>) -> Self {
+        Self::Channel(sender)
+    }
+}
 
 impl Drop for DiskScheduler {
     fn drop(&mut self) {
         // We ignore the result because if the queue is poisoned,
         // the thread is likely already dead.
-        let _ = self.request_queue.put(None);
-        if let Some(handle) = self.background_thread.take() {
+        for queue in &self.request_queues {
+            let _ = queue.close();
+        }
+        for handle in self.background_threads.drain(..) {
             let _ = handle.join();
         }
     }
@@ -76,113 +365,487 @@ impl Drop for DiskScheduler {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::{
-        fs::{self, remove_file},
-        path::PathBuf,
-        sync::mpsc,
+    use crate::common::{
+        config::{DOCKBASE_PAGE_SIZE, INVALID_PAGE_ID},
+        exception::ExceptionType,
+        temp_db::TempDb,
     };
-    fn setup(db_name: &str) -> (DiskScheduler, PathBuf, PathBuf) {
-        let db_path = PathBuf::from(db_name);
-        let log_path = PathBuf::from(format!(
-            "{}.log",
-            db_path.file_stem().unwrap().to_str().unwrap()
-        ));
-        let _ = remove_file(&db_path);
-        let _ = remove_file(&log_path);
+    use std::{collections::HashMap, sync::mpsc};
 
-        let disk_manager = Arc::new(DiskManager::new(db_path.clone()).unwrap());
-        let disk_scheduler = DiskScheduler::new(disk_manager);
-        (disk_scheduler, db_path, log_path)
+    fn setup(db_name: &str) -> (DiskScheduler, TempDb) {
+        setup_with_workers(db_name, 1)
     }
 
-    fn teardown(db_path: PathBuf, log_path: PathBuf) {
-        let _ = fs::remove_file(db_path);
-        let _ = fs::remove_file(log_path);
+    fn setup_with_workers(db_name: &str, num_workers: usize) -> (DiskScheduler, TempDb) {
+        let db = TempDb::new(db_name);
+        let disk_manager = Arc::new(DiskManager::new(db.get_path().into()).unwrap());
+        let options = DiskSchedulerOptions {
+            num_workers,
+            ..Default::default()
+        };
+        let disk_scheduler = DiskScheduler::with_options(disk_manager, options);
+        (disk_scheduler, db)
     }
 
     #[test]
     fn test_basic_read_write() {
-        let (disk_scheduler, db_path, log_path) = setup("test_scheduler_rw.db");
+        let (disk_scheduler, _db) = setup("test_scheduler_rw.db");
         let page_id: PageId = 10;
-        let mut buffer = [0; DOCKBASE_PAGE_SIZE];
+        let mut buffer = PageBuffer::new();
         let message = b"Hello Dockbase";
         buffer[..message.len()].copy_from_slice(message);
-        let (tx, rx) = mpsc::channel::<bool>();
+        let (tx, rx) = mpsc::channel::<DiskCompletion>();
         let write_request = DiskRequest {
             request_type: RequestType::Write,
-            data: buffer.as_mut_ptr(),
+            data: buffer,
             page_id,
-            callback: tx.clone(),
+            priority: RequestPriority::Foreground,
+            callback: tx.clone().into(),
         };
         let _ = disk_scheduler.schedule(vec![write_request]);
-        assert!(rx.recv().unwrap());
-        let mut read_buffer = [0; DOCKBASE_PAGE_SIZE];
+        let completion = rx.recv().unwrap();
+        assert!(completion.result.is_ok());
+        assert_eq!(completion.page_id, page_id);
+        assert_eq!(&completion.data[..message.len()], message);
+
         let read_request = DiskRequest {
             request_type: RequestType::Read,
-            data: read_buffer.as_mut_ptr(),
+            data: PageBuffer::new(),
             page_id,
-            callback: tx.clone(),
+            priority: RequestPriority::Foreground,
+            callback: tx.clone().into(),
         };
         let _ = disk_scheduler.schedule(vec![read_request]);
-        assert!(rx.recv().unwrap());
-        assert_eq!(&read_buffer[..message.len()], message);
-        teardown(db_path, log_path);
+        let completion = rx.recv().unwrap();
+        assert!(completion.result.is_ok());
+        assert_eq!(&completion.data[..message.len()], message);
+    }
+
+    #[test]
+    fn test_same_page_order() {
+        let (disk_scheduler, _db) = setup("test_scheduler_order.db");
+        let (tx, rx) = mpsc::channel::<DiskCompletion>();
+        let mut first = PageBuffer::new();
+        first[..5].copy_from_slice(b"first");
+        let mut second = PageBuffer::new();
+        second[..6].copy_from_slice(b"second");
+
+        let request = |request_type, data: &PageBuffer| DiskRequest {
+            request_type,
+            data: data.clone(),
+            page_id: 3,
+            priority: RequestPriority::Foreground,
+            callback: tx.clone().into(),
+        };
+        disk_scheduler
+            .schedule(vec![
+                request(RequestType::Write, &first),
+                request(RequestType::Read, &PageBuffer::new()),
+                request(RequestType::Write, &second),
+                request(RequestType::Read, &PageBuffer::new()),
+            ])
+            .unwrap();
+        let completions: Vec<DiskCompletion> = (0..4).map(|_| rx.recv().unwrap()).collect();
+        assert!(
+            completions
+                .iter()
+                .all(|completion| completion.result.is_ok())
+        );
+        assert_eq!(&completions[1].data[..], &first[..]);
+        assert_eq!(&completions[3].data[..], &second[..]);
+    }
+
+    #[test]
+    fn test_multiple_workers() {
+        let (disk_scheduler, _db) = setup_with_workers("test_scheduler_workers.db", 4);
+        assert_eq!(disk_scheduler.get_num_workers(), 4);
+        let num_pages = 1000;
+        let (tx, rx) = mpsc::channel::<DiskCompletion>();
+
+        // Every page is written twice in one batch; the second version must
+        // win on every worker.
+        let mut requests = Vec::new();
+        for version in 0..2 {
+            for i in 0..num_pages {
+                let mut buffer = PageBuffer::new();
+                let msg = format!("Page {i} version {version}");
+                buffer[..msg.len()].copy_from_slice(msg.as_bytes());
+                requests.push(DiskRequest {
+                    request_type: RequestType::Write,
+                    data: buffer,
+                    page_id: i as PageId,
+                    priority: RequestPriority::Foreground,
+                    callback: tx.clone().into(),
+                });
+            }
+        }
+        let (read_tx, read_rx) = mpsc::channel::<DiskCompletion>();
+        for i in 0..num_pages {
+            requests.push(DiskRequest {
+                request_type: RequestType::Read,
+                data: PageBuffer::new(),
+                page_id: i as PageId,
+                priority: RequestPriority::Foreground,
+                callback: read_tx.clone().into(),
+            });
+        }
+        disk_scheduler.schedule(requests).unwrap();
+        for _ in 0..2 * num_pages {
+            assert!(rx.recv().unwrap().result.is_ok());
+        }
+        for _ in 0..num_pages {
+            let completion = read_rx.recv().unwrap();
+            assert!(completion.result.is_ok());
+            let expected = format!("Page {} version 1", completion.page_id);
+            assert_eq!(&completion.data[..expected.len()], expected.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_schedule_async() {
+        let (disk_scheduler, _db) = setup("test_scheduler_async.db");
+        let writes: Vec<DiskFuture> = (0..32)
+            .map(|i| {
+                let mut page = PageBuffer::new();
+                page[..4].copy_from_slice(&(i as u32).to_le_bytes());
+                disk_scheduler.schedule_async(RequestType::Write, i, page)
+            })
+            .collect();
+        disk_future::wait_all(writes).unwrap();
+
+        let reads: Vec<DiskFuture> = (0..32)
+            .map(|i| disk_scheduler.schedule_async(RequestType::Read, i, PageBuffer::new()))
+            .collect();
+        let pages = disk_future::wait_all(reads).unwrap();
+        for (i, page) in pages.iter().enumerate() {
+            assert_eq!(&page[..4], &(i as u32).to_le_bytes());
+        }
+
+        let missing = disk_scheduler.schedule_async(RequestType::Read, 999, PageBuffer::new());
+        assert!(matches!(
+            missing.wait(),
+            Err(DiskError {
+                error: Exception::Invalid(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_failed_read_returns_buffer() {
+        let (disk_scheduler, _db) = setup("test_scheduler_failed_read.db");
+        let mut page = PageBuffer::new();
+        page[..4].copy_from_slice(b"mine");
+        let buffer_ptr = page.as_ptr();
+        let error = disk_scheduler
+            .schedule_async(RequestType::Read, 999, page)
+            .wait()
+            .unwrap_err();
+        assert!(matches!(error.error, Exception::Invalid(_)));
+        // The same buffer comes back and can be used for the next request.
+        assert_eq!(error.data.as_ptr(), buffer_ptr);
+        assert_eq!(&error.data[..4], b"mine");
+        disk_scheduler
+            .schedule_async(RequestType::Write, 1, error.data)
+            .wait()
+            .unwrap();
+        drop(disk_scheduler);
+    }
+
+    #[test]
+    fn test_delete_request() {
+        let (disk_scheduler, _db) = setup("test_scheduler_delete.db");
+        let (tx, rx) = mpsc::channel();
+        let request = |request_type, data| DiskRequest {
+            request_type,
+            data,
+            page_id: 4,
+            priority: RequestPriority::Foreground,
+            callback: tx.clone().into(),
+        };
+        disk_scheduler
+            .schedule(vec![
+                request(RequestType::Write, PageBuffer::new()),
+                request(RequestType::Delete, PageBuffer::empty()),
+                request(RequestType::Read, PageBuffer::new()),
+            ])
+            .unwrap();
+        let completions: Vec<DiskCompletion> = (0..3).map(|_| rx.recv().unwrap()).collect();
+        assert!(completions[0].result.is_ok());
+        assert_eq!(completions[1].request_type, RequestType::Delete);
+        assert!(completions[1].result.is_ok());
+        assert!(matches!(completions[2].result, Err(Exception::Invalid(_))));
+        assert_eq!(
+            disk_scheduler.get_disk_manager().get_num_deletes().unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_sync_and_barrier() {
+        let (disk_scheduler, _db) = setup_with_workers("test_scheduler_barrier.db", 4);
+        let num_pages = 200;
+        let (tx, rx) = mpsc::channel();
+        let (fence_tx, fence_rx) = mpsc::channel();
+        let fence = |request_type| DiskRequest {
+            request_type,
+            data: PageBuffer::empty(),
+            page_id: INVALID_PAGE_ID,
+            priority: RequestPriority::Foreground,
+            callback: DiskCallback::from(fence_tx.clone()),
+        };
+
+        let mut requests: Vec<DiskRequest> = (0..num_pages)
+            .map(|i| DiskRequest {
+                request_type: RequestType::Write,
+                data: PageBuffer::new(),
+                page_id: i,
+                priority: RequestPriority::Foreground,
+                callback: tx.clone().into(),
+            })
+            .collect();
+        requests.push(fence(RequestType::Barrier));
+        disk_scheduler.schedule(requests).unwrap();
+
+        // Every write completes before the barrier does.
+        let completion: DiskCompletion = fence_rx.recv().unwrap();
+        assert_eq!(completion.request_type, RequestType::Barrier);
+        assert!(completion.result.is_ok());
+        assert_eq!(rx.try_iter().count(), num_pages as usize);
+
+        let disk_manager = disk_scheduler.get_disk_manager();
+        let syncs = disk_manager.get_num_syncs().unwrap();
+        disk_scheduler
+            .schedule(vec![fence(RequestType::Sync)])
+            .unwrap();
+        let completion = fence_rx.recv().unwrap();
+        assert_eq!(completion.request_type, RequestType::Sync);
+        assert!(completion.result.is_ok());
+        assert!(disk_manager.get_num_syncs().unwrap() > syncs);
+
+        let barrier = disk_scheduler.schedule_async(
+            RequestType::Barrier,
+            INVALID_PAGE_ID,
+            PageBuffer::empty(),
+        );
+        assert!(barrier.wait().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_coalescing_scheduler() {
+        let db = TempDb::new("test_scheduler_coalesce.db");
+        let disk_manager = Arc::new(DiskManager::new(db.get_path().into()).unwrap());
+        let options = DiskSchedulerOptions {
+            num_workers: 2,
+            coalesce: true,
+            ..Default::default()
+        };
+        let disk_scheduler = DiskScheduler::with_options(disk_manager, options);
+        assert_eq!(disk_scheduler.get_backend(), SchedulerBackend::Blocking);
+
+        let num_pages = 200;
+        let page = |page_id: PageId, version: u8| {
+            let mut buffer = PageBuffer::new();
+            buffer[..4].copy_from_slice(&page_id.to_le_bytes());
+            buffer[4] = version;
+            buffer
+        };
+        let (tx, rx) = mpsc::channel::<DiskCompletion>();
+        let (read_tx, read_rx) = mpsc::channel::<DiskCompletion>();
+        let mut requests = Vec::new();
+        for version in 0..3 {
+            for page_id in 0..num_pages {
+                requests.push(DiskRequest {
+                    request_type: RequestType::Write,
+                    data: page(page_id, version),
+                    page_id,
+                    priority: RequestPriority::Foreground,
+                    callback: tx.clone().into(),
+                });
+            }
+            for page_id in 0..num_pages {
+                requests.push(DiskRequest {
+                    request_type: RequestType::Read,
+                    data: PageBuffer::new(),
+                    page_id,
+                    priority: RequestPriority::Foreground,
+                    callback: read_tx.clone().into(),
+                });
+            }
+        }
+        disk_scheduler.schedule(requests).unwrap();
+
+        for _ in 0..3 * num_pages {
+            assert!(rx.recv().unwrap().result.is_ok());
+        }
+        let mut versions_seen = HashMap::new();
+        for _ in 0..3 * num_pages {
+            let completion = read_rx.recv().unwrap();
+            assert!(completion.result.is_ok());
+            let version = completion.data[4];
+            assert_eq!(&completion.data[..], &page(completion.page_id, version)[..]);
+            // Reads of a page complete in order, each after its write.
+            let last = versions_seen.insert(completion.page_id, version);
+            assert_eq!(last.map_or(0, |last| last + 1), version);
+        }
+        drop(disk_scheduler);
+    }
+
+    #[test]
+    fn test_foreground_overtakes_background() {
+        let (disk_scheduler, _db) = setup("test_scheduler_priority.db");
+        disk_scheduler
+            .schedule_async(RequestType::Write, 0, PageBuffer::new())
+            .wait()
+            .unwrap();
+
+        let (tx, rx) = mpsc::channel::<DiskCompletion>();
+        let request = |request_type, page_id, priority| DiskRequest {
+            request_type,
+            data: PageBuffer::new(),
+            page_id,
+            priority,
+            callback: tx.clone().into(),
+        };
+        let num_writes = 1000;
+        let mut requests: Vec<DiskRequest> = (1..=num_writes)
+            .map(|page_id| request(RequestType::Write, page_id, RequestPriority::Background))
+            .collect();
+        requests.push(request(RequestType::Read, 0, RequestPriority::Foreground));
+        disk_scheduler.schedule(requests).unwrap();
+
+        let completions: Vec<DiskCompletion> =
+            (0..=num_writes).map(|_| rx.recv().unwrap()).collect();
+        assert!(
+            completions
+                .iter()
+                .all(|completion| completion.result.is_ok())
+        );
+        let read = completions
+            .iter()
+            .position(|completion| completion.request_type == RequestType::Read)
+            .unwrap();
+        assert!(read < num_writes as usize / 2, "read completed at {read}");
+    }
+
+    #[test]
+    fn test_backpressure() {
+        let db = TempDb::new("test_scheduler_backpressure.db");
+        let disk_manager = Arc::new(DiskManager::new(db.get_path().into()).unwrap());
+        let options = DiskSchedulerOptions {
+            num_workers: 4,
+            queue_capacity: 2,
+            ..Default::default()
+        };
+        let disk_scheduler = DiskScheduler::with_options(disk_manager, options);
+
+        let num_pages: PageId = 500;
+        let futures: Vec<DiskFuture> = (0..num_pages)
+            .map(|page_id| {
+                let mut buffer = PageBuffer::new();
+                buffer[..4].copy_from_slice(&page_id.to_le_bytes());
+                disk_scheduler.schedule_async(RequestType::Write, page_id, buffer)
+            })
+            .collect();
+        let sync = disk_scheduler.schedule_async(RequestType::Sync, 0, PageBuffer::empty());
+        disk_future::wait_all(futures).unwrap();
+        sync.wait().unwrap();
+
+        let futures: Vec<DiskFuture> = (0..num_pages)
+            .map(|page_id| {
+                disk_scheduler.schedule_async(RequestType::Read, page_id, PageBuffer::new())
+            })
+            .collect();
+        let pages = disk_future::wait_all(futures).unwrap();
+        for (page_id, page) in (0..num_pages).zip(&pages) {
+            assert_eq!(&page[..4], &page_id.to_le_bytes());
+        }
+        drop(disk_scheduler);
+    }
+
+    #[test]
+    fn test_backend() {
+        let (disk_scheduler, _db) = setup("test_scheduler_backend.db");
+        // With io_uring, the kernel may refuse it, in which case the
+        // scheduler falls back to blocking I/O.
+        if !cfg!(all(target_os = "linux", feature = "io-uring")) {
+            assert_eq!(disk_scheduler.get_backend(), SchedulerBackend::Blocking);
+        }
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    #[test]
+    fn test_uring_rejects_short_read_buffer() {
+        let (disk_scheduler, _db) = setup("test_scheduler_short_read.db");
+        disk_scheduler
+            .schedule_async(RequestType::Write, 0, PageBuffer::new())
+            .wait()
+            .unwrap();
+        let read = disk_scheduler.schedule_async(RequestType::Read, 0, PageBuffer::empty());
+        if disk_scheduler.get_backend() == SchedulerBackend::IoUring {
+            assert!(matches!(
+                read.wait().unwrap_err().error,
+                Exception::Invalid(_)
+            ));
+        }
+        drop(disk_scheduler);
     }
 
     #[test]
     fn test_scheduler_stress() {
-        let (disk_scheduler, db_path, log_path) = setup("test_scheduler_stress.db");
+        let (disk_scheduler, _db) = setup("test_scheduler_stress.db");
         let num_pages = 100000;
-        let (tx, rx) = mpsc::channel::<bool>();
+        let (tx, rx) = mpsc::channel::<DiskCompletion>();
 
-        let mut buffers = Vec::new();
         let mut requests = Vec::new();
 
         for i in 0..num_pages {
-            let mut buffer = Box::new([0u8; DOCKBASE_PAGE_SIZE]);
+            let mut buffer = PageBuffer::new();
             let msg = format!("Data for page {}", i);
             buffer[..msg.len()].copy_from_slice(msg.as_bytes());
 
             requests.push(DiskRequest {
                 request_type: RequestType::Write,
-                data: buffer.as_mut_ptr(),
+                data: buffer,
                 page_id: i as PageId,
-                callback: tx.clone(),
+                priority: RequestPriority::Foreground,
+                callback: tx.clone().into(),
             });
-            buffers.push(buffer); // Keep buffers alive on the heap
         }
 
         disk_scheduler.schedule(requests).unwrap();
 
         for _ in 0..num_pages {
-            assert!(rx.recv().unwrap());
+            assert!(rx.recv().unwrap().result.is_ok());
         }
 
         // Read back a few random pages
         let test_pages = vec![0, 50, 99];
         for pid in test_pages {
-            let mut read_buf = [0u8; DOCKBASE_PAGE_SIZE];
             let (read_tx, read_rx) = mpsc::channel();
 
             disk_scheduler
                 .schedule(vec![DiskRequest {
                     request_type: RequestType::Read,
-                    data: read_buf.as_mut_ptr(),
+                    data: PageBuffer::new(),
                     page_id: pid as PageId,
-                    callback: read_tx,
+                    priority: RequestPriority::Foreground,
+                    callback: read_tx.into(),
                 }])
                 .unwrap();
 
-            assert!(read_rx.recv().unwrap());
+            let completion = read_rx.recv().unwrap();
+            assert!(completion.result.is_ok());
             let expected_msg = format!("Data for page {}", pid);
-            assert_eq!(&read_buf[..expected_msg.len()], expected_msg.as_bytes());
+            assert_eq!(
+                &completion.data[..expected_msg.len()],
+                expected_msg.as_bytes()
+            );
         }
-
-        teardown(db_path, log_path);
     }
     #[test]
     fn test_scheduler_concurrency_and_error() {
-        let (disk_scheduler, db_path, log_path) = setup("test_concurrency.db");
+        let (disk_scheduler, _db) = setup("test_concurrency.db");
         let disk_scheduler = Arc::new(disk_scheduler); // Shadow with Arc for thread sharing
         let num_threads = 8;
         let requests_per_thread = 50;
@@ -192,27 +855,40 @@ mod tests {
             let thread_scheduler = Arc::clone(&disk_scheduler);
             let handle = thread::spawn(move || {
                 let (tx, rx) = mpsc::channel();
-                let mut buffer = [0u8; DOCKBASE_PAGE_SIZE];
+                let mut buffer = PageBuffer::new();
 
                 for i in 0..requests_per_thread {
                     let page_id = (t * requests_per_thread + i) as PageId;
 
+                    // The error case reads a page that was never written.
                     let is_error_case = i == 25;
-                    let target_page_id = if is_error_case { 999_999_999 } else { page_id };
+                    let (request_type, target_page_id) = if is_error_case {
+                        (RequestType::Read, 999_999_999)
+                    } else {
+                        (RequestType::Write, page_id)
+                    };
 
                     let request = DiskRequest {
-                        request_type: RequestType::Write,
-                        data: buffer.as_mut_ptr(),
+                        request_type,
+                        data: buffer,
                         page_id: target_page_id,
-                        callback: tx.clone(),
+                        priority: RequestPriority::Foreground,
+                        callback: tx.clone().into(),
                     };
 
                     thread_scheduler.schedule(vec![request]).unwrap();
-                    let success = rx.recv().unwrap();
+                    let completion: DiskCompletion = rx.recv().unwrap();
+                    assert_eq!(completion.page_id, target_page_id);
+                    assert_eq!(completion.request_type, request_type);
 
-                    if !is_error_case {
-                        assert!(success);
+                    if is_error_case {
+                        let error = completion.result.unwrap_err();
+                        assert_eq!(error.get_type(), ExceptionType::Invalid);
+                    } else {
+                        assert!(completion.result.is_ok());
                     }
+                    // The buffer comes back with the completion and is reused.
+                    buffer = completion.data;
                 }
             });
             thread_handles.push(handle);
@@ -221,30 +897,45 @@ mod tests {
         for handle in thread_handles {
             handle.join().unwrap();
         }
-        teardown(db_path, log_path);
+    }
+
+    #[test]
+    fn test_oversized_buffer_is_rejected() {
+        let (disk_scheduler, _db) = setup("test_scheduler_oversized.db");
+        let (tx, rx) = mpsc::channel();
+        disk_scheduler
+            .schedule(vec![DiskRequest {
+                request_type: RequestType::Write,
+                data: PageBuffer::with_pages(2),
+                page_id: 0,
+                priority: RequestPriority::Foreground,
+                callback: tx.into(),
+            }])
+            .unwrap();
+        let completion = rx.recv().unwrap();
+        assert!(matches!(completion.result, Err(Exception::OutOfRange(_))));
+        assert_eq!(completion.data.len(), 2 * DOCKBASE_PAGE_SIZE);
     }
 
     #[test]
     fn test_scheduler_drop_cleanup() {
-        let (db_path, log_path) = {
-            let (disk_scheduler, db_path, log_path) = setup("test_drop.db");
+        let _db = {
+            let (disk_scheduler, db) = setup("test_drop.db");
 
             let (tx, rx) = mpsc::channel();
-            let mut buffer = [0u8; DOCKBASE_PAGE_SIZE];
             let request = DiskRequest {
                 request_type: RequestType::Write,
-                data: buffer.as_mut_ptr(),
+                data: PageBuffer::new(),
                 page_id: 0,
-                callback: tx,
+                priority: RequestPriority::Foreground,
+                callback: tx.into(),
             };
 
             disk_scheduler.schedule(vec![request]).unwrap();
-            assert!(rx.recv().unwrap());
+            assert!(rx.recv().unwrap().result.is_ok());
 
             // disk_scheduler dropped here
-            (db_path, log_path)
+            db
         };
-
-        teardown(db_path, log_path);
     }
 }
@@ -0,0 +1,247 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    os::unix::fs::FileExt,
+    path::Path,
+    sync::{Condvar, Mutex},
+};
+
+use crate::common::{
+    checksum::crc32c,
+    config::{DOCKBASE_PAGE_SIZE, DOUBLE_WRITE_BUFFER_SIZE, INVALID_PAGE_ID, PageId},
+    exception::Exception,
+};
+use crate::storage::disk::{
+    disk_manager::open_page_file, page_buffer::PageBuffer, vectored_io::write_coalesced,
+};
+
+// Every page image is copied into a double-write record before it is written
+// to its home location. A record is one header page followed by the image,
+// so a crash that tears the home write leaves an intact copy behind.
+const RECORD_SIZE: usize = 2 * DOCKBASE_PAGE_SIZE;
+const RECORD_MAGIC: u32 = 0x4457_4252;
+
+pub(crate) struct DoubleWriteBuffer {
+    file: File,
+    slots: Mutex<Slots>,
+    slot_released: Condvar,
+}
+
+struct Slots {
+    // Slots are recycled in FIFO order so records survive as long as possible.
+    free: VecDeque<usize>,
+    next_sequence: u64,
+}
+
+/// A page image recovered from the double-write file.
+pub(crate) struct DoubleWriteRecord {
+    pub sequence: u64,
+    pub offset: usize,
+    pub page_id: PageId,
+    pub checksum: u32,
+    /// Checksum of the page this image replaced, zero for a new page.
+    pub prev_checksum: u32,
+    pub image: PageBuffer,
+}
+
+/// Keeps a record's slot reserved until the home write it protects is done.
+pub(crate) struct DoubleWriteGuard<'a> {
+    buffer: &'a DoubleWriteBuffer,
+    slot: usize,
+    record: PageBuffer,
+}
+
+impl DoubleWriteBuffer {
+    pub fn open(path: &Path, direct_io: bool) -> Result<Self, Exception> {
+        let (file, _) = open_page_file(path, direct_io)?;
+        let file_size = (DOUBLE_WRITE_BUFFER_SIZE * RECORD_SIZE) as u64;
+        if file.metadata()?.len() < file_size {
+            file.set_len(file_size)?;
+        }
+
+        let buffer = Self {
+            file,
+            slots: Mutex::new(Slots {
+                free: (0..DOUBLE_WRITE_BUFFER_SIZE).collect(),
+                next_sequence: 1,
+            }),
+            slot_released: Condvar::new(),
+        };
+        let last_sequence = buffer
+            .records()?
+            .values()
+            .map(|record| record.sequence)
+            .max()
+            .unwrap_or(0);
+        buffer.slots.lock()?.next_sequence = last_sequence + 1;
+        Ok(buffer)
+    }
+
+    /// Stages `image` for the page at `offset` and writes the record. The
+    /// slot must stay reserved until the home write completes, so the
+    /// returned guard is expected to outlive it.
+    pub fn write(
+        &self,
+        offset: usize,
+        page_id: PageId,
+        image: &[u8],
+        checksum: u32,
+        prev_checksum: u32,
+    ) -> Result<DoubleWriteGuard<'_>, Exception> {
+        let Some(guard) = self.stage(offset, page_id, image, checksum, prev_checksum, true)? else {
+            unreachable!("a waiting stage always reserves a slot");
+        };
+        self.write_staged(&guard)?;
+        Ok(guard)
+    }
+
+    /// Reserves a slot and encodes the record without writing it, for
+    /// callers that submit the I/O themselves. Directory pages are staged
+    /// with `INVALID_PAGE_ID`. If every slot is taken, waits for one to be
+    /// released, or returns `None` when `wait` is false.
+    pub fn stage(
+        &self,
+        offset: usize,
+        page_id: PageId,
+        image: &[u8],
+        checksum: u32,
+        prev_checksum: u32,
+        wait: bool,
+    ) -> Result<Option<DoubleWriteGuard<'_>>, Exception> {
+        let Some((slot, sequence)) = self.acquire(page_id == INVALID_PAGE_ID, wait)? else {
+            return Ok(None);
+        };
+
+        let mut record = PageBuffer::with_pages(RECORD_SIZE / DOCKBASE_PAGE_SIZE);
+        record[0..4].copy_from_slice(&RECORD_MAGIC.to_le_bytes());
+        record[4..12].copy_from_slice(&sequence.to_le_bytes());
+        record[12..20].copy_from_slice(&(offset as u64).to_le_bytes());
+        record[20..24].copy_from_slice(&page_id.to_le_bytes());
+        record[24..28].copy_from_slice(&checksum.to_le_bytes());
+        record[28..32].copy_from_slice(&prev_checksum.to_le_bytes());
+        let header_checksum = crc32c(&record[0..32]);
+        record[32..36].copy_from_slice(&header_checksum.to_le_bytes());
+        record[DOCKBASE_PAGE_SIZE..].copy_from_slice(image);
+        Ok(Some(DoubleWriteGuard {
+            buffer: self,
+            slot,
+            record,
+        }))
+    }
+
+    pub fn write_staged(&self, guard: &DoubleWriteGuard<'_>) -> Result<(), Exception> {
+        Ok(self.file.write_all_at(guard.record(), guard.position())?)
+    }
+
+    /// Writes several staged records, merging those in adjacent slots into
+    /// one call. Returns how many calls that saved.
+    pub fn write_staged_all<'a>(
+        &self,
+        guards: impl IntoIterator<Item = &'a DoubleWriteGuard<'a>>,
+    ) -> Result<usize, Exception> {
+        let mut writes: Vec<(u64, &[u8])> = guards
+            .into_iter()
+            .map(|guard| (guard.position(), guard.record()))
+            .collect();
+        Ok(write_coalesced(&self.file, &mut writes)?)
+    }
+
+    pub fn sync_data(&self) -> Result<(), Exception> {
+        Ok(self.file.sync_data()?)
+    }
+
+    pub fn sync_all(&self) -> Result<(), Exception> {
+        Ok(self.file.sync_all()?)
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    pub fn try_clone_file(&self) -> Result<File, Exception> {
+        Ok(self.file.try_clone()?)
+    }
+
+    /// Returns the newest intact record for every page offset. Records torn
+    /// by a crash fail their checksums and are skipped.
+    pub fn records(&self) -> Result<HashMap<usize, DoubleWriteRecord>, Exception> {
+        let mut records: HashMap<usize, DoubleWriteRecord> = HashMap::new();
+        let mut buffer = PageBuffer::with_pages(RECORD_SIZE / DOCKBASE_PAGE_SIZE);
+        for slot in 0..DOUBLE_WRITE_BUFFER_SIZE {
+            self.file
+                .read_exact_at(&mut buffer, (slot * RECORD_SIZE) as u64)?;
+
+            let Some(record) = decode_record(&buffer) else {
+                continue;
+            };
+            match records.get(&record.offset) {
+                Some(newest) if newest.sequence > record.sequence => {}
+                _ => {
+                    records.insert(record.offset, record);
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    // One slot is held back for directory pages: they are written while a
+    // data record is still reserved, so data writers alone must never be
+    // able to exhaust the buffer.
+    fn acquire(&self, is_directory: bool, wait: bool) -> Result<Option<(usize, u64)>, Exception> {
+        let reserved = if is_directory { 0 } else { 1 };
+        let mut slots = self.slots.lock()?;
+        while slots.free.len() <= reserved {
+            if !wait {
+                return Ok(None);
+            }
+            slots = self.slot_released.wait(slots)?;
+        }
+        let slot = slots.free.pop_front().unwrap();
+        let sequence = slots.next_sequence;
+        slots.next_sequence += 1;
+        Ok(Some((slot, sequence)))
+    }
+}
+
+impl DoubleWriteGuard<'_> {
+    /// The encoded record, ready to be written at `position`.
+    pub fn record(&self) -> &[u8] {
+        &self.record
+    }
+
+    pub fn position(&self) -> u64 {
+        (self.slot * RECORD_SIZE) as u64
+    }
+}
+
+impl Drop for DoubleWriteGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut slots) = self.buffer.slots.lock() {
+            slots.free.push_back(self.slot);
+            self.buffer.slot_released.notify_all();
+        }
+    }
+}
+
+fn decode_record(buffer: &[u8]) -> Option<DoubleWriteRecord> {
+    let read_u32 = |at: usize| u32::from_le_bytes(buffer[at..at + 4].try_into().unwrap());
+    let read_u64 = |at: usize| u64::from_le_bytes(buffer[at..at + 8].try_into().unwrap());
+
+    if read_u32(0) != RECORD_MAGIC || read_u32(32) != crc32c(&buffer[0..32]) {
+        return None;
+    }
+    let image = &buffer[DOCKBASE_PAGE_SIZE..];
+    let checksum = read_u32(24);
+    if crc32c(image) != checksum {
+        return None;
+    }
+    Some(DoubleWriteRecord {
+        sequence: read_u64(4),
+        offset: read_u64(12) as usize,
+        page_id: PageId::from_le_bytes(buffer[20..24].try_into().unwrap()),
+        checksum,
+        prev_checksum: read_u32(28),
+        image: PageBuffer::from_slice(image),
+    })
+}
@@ -1,2 +1,15 @@
+// Pages are read and written with pread/pwrite and their vectored forms, so
+// the disk layer only builds on unix targets.
+#[cfg(not(unix))]
+compile_error!("dockbase only supports unix targets");
+
+pub(crate) mod batch_worker;
+pub mod disk_future;
 pub mod disk_manager;
 pub mod disk_scheduler;
+pub mod double_write;
+pub mod page_buffer;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub(crate) mod uring_worker;
+pub(crate) mod vectored_io;
+pub(crate) mod worker_queue;
@@ -0,0 +1,117 @@
+use std::{
+    alloc::{self, Layout},
+    fmt,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+
+use crate::common::config::DOCKBASE_PAGE_SIZE;
+
+/// Alignment of every `PageBuffer`. `O_DIRECT` requires buffers aligned to
+/// the logical block size of the device, which never exceeds 4 KiB in
+/// practice.
+pub const PAGE_BUFFER_ALIGNMENT: usize = 4096;
+
+/// A zero-initialised, heap-allocated buffer of whole pages, aligned so it
+/// can be handed to the kernel with direct I/O.
+pub struct PageBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+impl PageBuffer {
+    pub fn new() -> Self {
+        Self::with_pages(1)
+    }
+
+    pub fn with_pages(num_pages: usize) -> Self {
+        assert!(num_pages > 0, "a page buffer holds at least one page");
+        let layout = Self::layout(num_pages * DOCKBASE_PAGE_SIZE);
+        // SAFETY: the layout has a non-zero size.
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let Some(ptr) = NonNull::new(ptr) else {
+            alloc::handle_alloc_error(layout);
+        };
+        Self {
+            ptr,
+            len: layout.size(),
+        }
+    }
+
+    /// A buffer without any pages, for requests that carry no data.
+    pub fn empty() -> Self {
+        Self {
+            ptr: NonNull::dangling(),
+            len: 0,
+        }
+    }
+
+    pub fn from_slice(data: &[u8]) -> Self {
+        let mut buffer = Self::with_pages(data.len().div_ceil(DOCKBASE_PAGE_SIZE).max(1));
+        buffer[..data.len()].copy_from_slice(data);
+        buffer
+    }
+
+    /// Whether `data` can be used for direct I/O as is.
+    pub fn is_aligned(data: &[u8]) -> bool {
+        (data.as_ptr() as usize).is_multiple_of(PAGE_BUFFER_ALIGNMENT)
+            && data.len().is_multiple_of(PAGE_BUFFER_ALIGNMENT)
+    }
+
+    fn layout(len: usize) -> Layout {
+        Layout::from_size_align(len, PAGE_BUFFER_ALIGNMENT).unwrap()
+    }
+}
+
+impl Default for PageBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for PageBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: `ptr` owns `len` initialised bytes for the buffer's lifetime.
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for PageBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: as above, and `&mut self` guarantees exclusive access.
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Clone for PageBuffer {
+    fn clone(&self) -> Self {
+        match self.len {
+            0 => Self::empty(),
+            _ => Self::from_slice(self),
+        }
+    }
+}
+
+impl fmt::Debug for PageBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PageBuffer")
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl Drop for PageBuffer {
+    fn drop(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+        // SAFETY: the pointer was allocated in `with_pages` with this layout.
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), Self::layout(self.len)) }
+    }
+}
+
+// SAFETY: the buffer uniquely owns its allocation, like a `Box<[u8]>`.
+unsafe impl Send for PageBuffer {}
+unsafe impl Sync for PageBuffer {}
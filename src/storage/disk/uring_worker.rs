@@ -0,0 +1,495 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    io,
+    os::fd::AsRawFd,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use io_uring::{IoUring, opcode, squeue, types};
+
+use crate::common::{
+    config::{DOCKBASE_PAGE_SIZE, DOUBLE_WRITE_BUFFER_SIZE, PageId},
+    exception::Exception,
+};
+use crate::log_warn;
+use crate::storage::disk::{
+    disk_manager::{DiskManager, PendingRead, PendingWrite},
+    disk_scheduler::{DiskRequest, RequestType, WorkerTask},
+    worker_queue::WorkerQueue,
+};
+
+// Requests kept in flight at once. Every write holds a double-write slot
+// until it completes, so writes are limited further to leave slots for
+// directory pages and for writers that bypass the scheduler.
+const QUEUE_DEPTH: usize = 64;
+const MAX_WRITES_IN_FLIGHT: usize = DOUBLE_WRITE_BUFFER_SIZE / 2;
+// A write is submitted as a linked chain of at most three entries: the
+// double-write record, its sync and the page.
+const MAX_ENTRIES_PER_REQUEST: usize = 3;
+const RING_ENTRIES: u32 = (QUEUE_DEPTH * MAX_ENTRIES_PER_REQUEST) as u32;
+
+// The low bits of an entry's user data tell which step of a request it is,
+// the rest is the request's index in `UringWorker::in_flight`.
+const STEP_BITS: u64 = 2;
+const STEP_RECORD: u64 = 0;
+const STEP_PAGE: u64 = 1;
+const STEP_SYNC: u64 = 2;
+
+pub(crate) fn open_ring() -> io::Result<IoUring> {
+    IoUring::new(RING_ENTRIES)
+}
+
+/// Worker loop of the io_uring backend. Requests are staged through the disk
+/// manager, their I/O is submitted to the ring, and callbacks are sent from
+/// the completion queue. At most one request per page is in flight, so
+/// requests for the same page still complete in submission order.
+pub(crate) fn run_worker(ring: IoUring, disk_manager: Arc<DiskManager>, queue: Arc<WorkerQueue>) {
+    let mut worker = UringWorker::new(ring, &disk_manager);
+    let mut closed = false;
+    loop {
+        while !closed && worker.backlog.len() < QUEUE_DEPTH {
+            // Only block on the queue when there is nothing to complete.
+            let next = match worker.is_idle() {
+                true => queue.get().map(Some),
+                false => queue.try_get(),
+            };
+            match next {
+                Ok(Some(Some(task))) => worker.backlog.push_back(task),
+                Ok(None) => break,
+                Ok(Some(None)) | Err(_) => closed = true,
+            }
+        }
+        worker.issue_backlog();
+        if worker.in_flight_count() == 0 {
+            if closed {
+                break;
+            }
+            continue;
+        }
+        worker.complete();
+    }
+}
+
+struct UringWorker<'a> {
+    ring: IoUring,
+    disk_manager: &'a DiskManager,
+    in_flight: Vec<Option<InFlight<'a>>>,
+    free_indexes: Vec<usize>,
+    busy_pages: HashSet<PageId>,
+    writes_in_flight: usize,
+    // Tasks taken off the queue that cannot be issued yet.
+    backlog: VecDeque<WorkerTask>,
+    // Requests with entries the kernel has not taken yet, oldest first,
+    // with how many of their entries are still queued.
+    unsubmitted: VecDeque<(usize, usize)>,
+    // Set once submitting to the ring has failed. The ring is then only
+    // drained, and new requests run as blocking I/O.
+    ring_failed: bool,
+}
+
+struct InFlight<'a> {
+    request: DiskRequest,
+    io: PageIo<'a>,
+    remaining_entries: usize,
+    error: Option<Exception>,
+}
+
+// Together with the request's own buffer, owns every buffer the kernel
+// reads from or writes to until the request completes.
+enum PageIo<'a> {
+    Read(PendingRead),
+    Write(PendingWrite<'a>),
+}
+
+impl<'a> UringWorker<'a> {
+    fn new(ring: IoUring, disk_manager: &'a DiskManager) -> Self {
+        Self {
+            ring,
+            disk_manager,
+            in_flight: Vec::new(),
+            free_indexes: Vec::new(),
+            busy_pages: HashSet::new(),
+            writes_in_flight: 0,
+            backlog: VecDeque::new(),
+            unsubmitted: VecDeque::new(),
+            ring_failed: false,
+        }
+    }
+
+    fn in_flight_count(&self) -> usize {
+        self.in_flight.len() - self.free_indexes.len()
+    }
+
+    fn is_idle(&self) -> bool {
+        self.in_flight_count() == 0 && self.backlog.is_empty()
+    }
+
+    /// Starts backlog requests in order, skipping those whose page is busy.
+    /// A skipped page stays blocked for the rest of the scan so that later
+    /// requests for it cannot overtake earlier ones. Nothing is started past
+    /// a fence, which is passed once everything before it has completed.
+    fn issue_backlog(&mut self) {
+        let mut blocked = HashSet::new();
+        let mut writes_stalled = false;
+        let mut position = 0;
+        while position < self.backlog.len() && self.in_flight_count() < QUEUE_DEPTH {
+            let request = match &self.backlog[position] {
+                WorkerTask::Request(request) => request,
+                WorkerTask::Fence(_) if position == 0 && self.in_flight_count() == 0 => {
+                    if let Some(WorkerTask::Fence(fence)) = self.backlog.pop_front() {
+                        fence.arrive(self.disk_manager);
+                    }
+                    continue;
+                }
+                WorkerTask::Fence(_) => break,
+            };
+            let is_write = matches!(request.request_type, RequestType::Write);
+            if self.busy_pages.contains(&request.page_id)
+                || blocked.contains(&request.page_id)
+                || (is_write && (writes_stalled || self.writes_in_flight >= MAX_WRITES_IN_FLIGHT))
+            {
+                blocked.insert(request.page_id);
+                position += 1;
+                continue;
+            }
+            let Some(WorkerTask::Request(request)) = self.backlog.remove(position) else {
+                continue;
+            };
+            if let Err(request) = self.start(request) {
+                writes_stalled = true;
+                blocked.insert(request.page_id);
+                self.backlog.insert(position, WorkerTask::Request(request));
+                position += 1;
+            }
+        }
+    }
+
+    /// Stages `request` and submits its I/O. A write is handed back if the
+    /// double-write buffer is full: the slots may be held by other workers,
+    /// so waiting for one while this worker has I/O left to reap could
+    /// deadlock. Only an idle worker waits.
+    fn start(&mut self, mut request: DiskRequest) -> Result<(), DiskRequest> {
+        let free_entries = {
+            let submission = self.ring.submission();
+            submission.capacity() - submission.len()
+        };
+        if free_entries < MAX_ENTRIES_PER_REQUEST {
+            self.submit(false);
+        }
+        if self.ring_failed {
+            let result = request.execute(self.disk_manager);
+            request.complete(result);
+            return Ok(());
+        }
+
+        let io = match request.request_type {
+            // The kernel fills a whole page, which must fit in the buffer.
+            RequestType::Read if request.data.len() < DOCKBASE_PAGE_SIZE => {
+                Err(Exception::Invalid("Read buffer is shorter than a page"))
+            }
+            RequestType::Read => self
+                .disk_manager
+                .begin_read(request.page_id)
+                .map(|pending| Some(PageIo::Read(pending))),
+            RequestType::Write => {
+                let wait = self.in_flight_count() == 0;
+                self.disk_manager
+                    .begin_write(request.page_id, &request.data, wait)
+                    .map(|pending| pending.map(PageIo::Write))
+            }
+            // Only touches metadata and the directory, so it runs inline.
+            RequestType::Delete | RequestType::Sync | RequestType::Barrier => {
+                let result = request.execute(self.disk_manager);
+                request.complete(result);
+                return Ok(());
+            }
+        };
+        let io = match io {
+            Ok(Some(io)) => io,
+            Ok(None) => return Err(request),
+            Err(error) => {
+                request.complete(Err(error));
+                return Ok(());
+            }
+        };
+
+        let index = match self.free_indexes.pop() {
+            Some(index) => index,
+            None => {
+                self.in_flight.push(None);
+                self.in_flight.len() - 1
+            }
+        };
+        if let PageIo::Write(_) = io {
+            self.writes_in_flight += 1;
+        }
+        self.busy_pages.insert(request.page_id);
+        let mut op = InFlight {
+            request,
+            io,
+            remaining_entries: 0,
+            error: None,
+        };
+        let entries = self.prepare_entries(index, &mut op);
+        op.remaining_entries = entries.len();
+        // SAFETY: the buffers behind the entries are owned by `op`, which
+        // stays in `in_flight` until every entry has completed, or, if the
+        // ring fails first, until it is certain the kernel never takes them.
+        // Moving `op` does not move the heap buffers themselves.
+        let pushed = unsafe { self.ring.submission().push_multiple(&entries) };
+        if pushed.is_err() {
+            op.error = Some(Exception::IO("io_uring submission queue is full"));
+            self.free_indexes.push(index);
+            self.finish(op);
+            return Ok(());
+        }
+        self.in_flight[index] = Some(op);
+        self.unsubmitted.push_back((index, entries.len()));
+        Ok(())
+    }
+
+    fn prepare_entries(&self, index: usize, op: &mut InFlight<'_>) -> Vec<squeue::Entry> {
+        let tag = |step: u64| ((index as u64) << STEP_BITS) | step;
+        let db_fd = types::Fd(self.disk_manager.page_file().as_raw_fd());
+        let mut entries = Vec::new();
+        match &op.io {
+            PageIo::Read(pending) => {
+                let page = &mut op.request.data;
+                let read = opcode::Read::new(db_fd, page.as_mut_ptr(), DOCKBASE_PAGE_SIZE as u32)
+                    .offset(pending.offset())
+                    .build();
+                entries.push(read.user_data(tag(STEP_PAGE)));
+            }
+            PageIo::Write(pending) => {
+                // The page may only be written once its record is on disk,
+                // so the entries are linked and run strictly in order.
+                let dwb_fd = types::Fd(self.disk_manager.double_write_file().as_raw_fd());
+                let record = pending.record();
+                let record_write = opcode::Write::new(
+                    dwb_fd,
+                    record.record().as_ptr(),
+                    record.record().len() as u32,
+                )
+                .offset(record.position())
+                .build();
+                entries.push(record_write.user_data(tag(STEP_RECORD)));
+                if self.disk_manager.sync_records() {
+                    let record_sync = opcode::Fsync::new(dwb_fd)
+                        .flags(types::FsyncFlags::DATASYNC)
+                        .build();
+                    entries.push(record_sync.user_data(tag(STEP_SYNC)));
+                }
+                let image = pending.image();
+                let page_write = opcode::Write::new(db_fd, image.as_ptr(), image.len() as u32)
+                    .offset(pending.offset())
+                    .build();
+                // Under `DataSyncPerWrite` the page is synced along with
+                // its directory when the write is finished.
+                entries.push(page_write.user_data(tag(STEP_PAGE)));
+                let last = entries.len() - 1;
+                for entry in &mut entries[..last] {
+                    *entry = entry.clone().flags(squeue::Flags::IO_LINK);
+                }
+            }
+        }
+        entries
+    }
+
+    /// Submits everything queued, waits for at least one completion and
+    /// finishes the requests whose entries have all completed.
+    fn complete(&mut self) {
+        match self.ring_failed {
+            false => self.submit(true),
+            // Entries left in a failed ring must never be submitted, so
+            // completions of those already taken are polled for instead.
+            true => thread::sleep(Duration::from_millis(1)),
+        }
+        let completions: Vec<(u64, i32)> = self
+            .ring
+            .completion()
+            .map(|entry| (entry.user_data(), entry.result()))
+            .collect();
+        for (user_data, result) in completions {
+            self.on_completion(user_data, result);
+        }
+    }
+
+    /// Hands queued entries to the kernel, waiting for a completion if
+    /// `wait` is set. A busy ring is retried on the next call, once more
+    /// completions have been reaped. Any other error fails every request the
+    /// kernel has not taken, and the ring is not submitted to again.
+    fn submit(&mut self, wait: bool) {
+        match self.ring.submit_and_wait(wait as usize) {
+            Ok(_) => {}
+            Err(error)
+                if matches!(
+                    error.raw_os_error(),
+                    Some(libc::EBUSY | libc::EAGAIN | libc::EINTR)
+                ) =>
+            {
+                thread::yield_now();
+            }
+            Err(error) => {
+                log_warn!("io_uring submission failed, using blocking I/O: {error}");
+                self.fail_unsubmitted(error);
+                return;
+            }
+        }
+
+        // The kernel takes entries in order, so whatever is still queued
+        // belongs to the newest requests.
+        let queued = self.ring.submission().len();
+        let mut taken = self
+            .unsubmitted
+            .iter()
+            .map(|&(_, count)| count)
+            .sum::<usize>()
+            - queued;
+        while taken > 0
+            && let Some((_, count)) = self.unsubmitted.front_mut()
+        {
+            let consumed = taken.min(*count);
+            *count -= consumed;
+            taken -= consumed;
+            if *count == 0 {
+                self.unsubmitted.pop_front();
+            }
+        }
+    }
+
+    fn fail_unsubmitted(&mut self, error: io::Error) {
+        let error = Exception::from(error);
+        while let Some((index, queued)) = self.unsubmitted.pop_front() {
+            let Some(op) = self.in_flight[index].as_mut() else {
+                continue;
+            };
+            op.error.get_or_insert(error.clone());
+            op.remaining_entries -= queued;
+            if op.remaining_entries == 0 {
+                let op = self.in_flight[index].take().unwrap();
+                self.free_indexes.push(index);
+                self.finish(op);
+            }
+        }
+        self.ring_failed = true;
+    }
+
+    fn on_completion(&mut self, user_data: u64, result: i32) {
+        let index = (user_data >> STEP_BITS) as usize;
+        let step = user_data & ((1 << STEP_BITS) - 1);
+        let Some(op) = self.in_flight[index].as_mut() else {
+            return;
+        };
+
+        let outcome = match (step, &op.io) {
+            _ if result < 0 => Err(io::Error::from_raw_os_error(-result).into()),
+            (STEP_SYNC, _) => {
+                self.disk_manager.count_sync();
+                Ok(())
+            }
+            (STEP_RECORD, PageIo::Write(pending))
+                if result as usize != pending.record().record().len() =>
+            {
+                Err(Exception::IO("Short write to the double-write buffer"))
+            }
+            (STEP_PAGE, PageIo::Write(_)) if result as usize != DOCKBASE_PAGE_SIZE => {
+                Err(Exception::IO("Short write to the page file"))
+            }
+            (STEP_PAGE, PageIo::Read(..)) if result == 0 => {
+                Err(Exception::IO("Read offset past end of file"))
+            }
+            (STEP_PAGE, PageIo::Read(_)) => {
+                op.request.data[result as usize..DOCKBASE_PAGE_SIZE].fill(0);
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if let Err(error) = outcome {
+            // Entries linked after a failed one are cancelled; keep the
+            // error that caused it.
+            op.error.get_or_insert(error);
+        }
+        op.remaining_entries -= 1;
+        if op.remaining_entries == 0 {
+            let op = self.in_flight[index].take().unwrap();
+            self.free_indexes.push(index);
+            self.finish(op);
+        }
+    }
+
+    fn finish(&mut self, op: InFlight<'a>) {
+        let InFlight {
+            mut request,
+            io,
+            error,
+            ..
+        } = op;
+        self.busy_pages.remove(&request.page_id);
+        let result = match io {
+            PageIo::Read(pending) => error.map_or(Ok(()), Err).and_then(|()| {
+                let page = &mut request.data[..DOCKBASE_PAGE_SIZE];
+                match self.disk_manager.finish_read(&pending, page)? {
+                    // The read raced with a write of the page; rare enough
+                    // to read it again in place.
+                    Some(_) => self.disk_manager.read_page(request.page_id, page),
+                    None => Ok(()),
+                }
+            }),
+            PageIo::Write(pending) => {
+                self.writes_in_flight -= 1;
+                // Dropping a failed write releases its record and slot.
+                error
+                    .map_or(Ok(()), Err)
+                    .and_then(|()| self.disk_manager.finish_write(pending))
+            }
+        };
+        request.complete(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::temp_db::TempDb;
+    use crate::storage::disk::{
+        disk_scheduler::{DiskCallback, RequestPriority},
+        page_buffer::PageBuffer,
+    };
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_failed_submission_fails_queued_requests() -> Result<(), Exception> {
+        let db = TempDb::new("test_uring_failed_submission.db");
+        let disk_manager = DiskManager::new(db.get_path().into())?;
+        // The kernel may not allow io_uring at all.
+        let Ok(ring) = open_ring() else {
+            return Ok(());
+        };
+        let mut worker = UringWorker::new(ring, &disk_manager);
+        let (sender, receiver) = mpsc::channel();
+        let request = |page_id| DiskRequest {
+            request_type: RequestType::Write,
+            data: PageBuffer::from_slice(&[7u8; DOCKBASE_PAGE_SIZE]),
+            page_id,
+            priority: RequestPriority::Foreground,
+            callback: DiskCallback::Channel(sender.clone()),
+        };
+
+        // A request still queued when the ring fails gets the error.
+        assert!(worker.start(request(0)).is_ok());
+        worker.fail_unsubmitted(io::Error::from_raw_os_error(libc::EINVAL));
+        assert!(receiver.recv().unwrap().result.is_err());
+        assert_eq!(worker.in_flight_count(), 0);
+
+        // Later requests bypass the ring.
+        assert!(worker.start(request(1)).is_ok());
+        assert!(receiver.recv().unwrap().result.is_ok());
+        let mut page = PageBuffer::new();
+        disk_manager.read_page(1, &mut page)?;
+        assert_eq!(page[0], 7);
+        drop(worker);
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    fs::File,
+    io::{self, IoSlice, IoSliceMut},
+    os::fd::AsRawFd,
+};
+
+// Positional vectored I/O through libc, which `FileExt` does not offer.
+// `IoSlice` and `IoSliceMut` are ABI compatible with `struct iovec`.
+
+/// Most buffers one `preadv` or `pwritev` call accepts.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) const IOV_MAX: usize = libc::UIO_MAXIOV as usize;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) const IOV_MAX: usize = libc::IOV_MAX as usize;
+
+/// Writes all of `bufs` back to back starting at `offset`, in calls of at
+/// most `IOV_MAX` buffers, retrying after short writes.
+pub(crate) fn write_all_vectored_at(
+    file: &File,
+    mut bufs: &mut [IoSlice<'_>],
+    mut offset: u64,
+) -> io::Result<()> {
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        // SAFETY: every slice in `bufs` is valid for reads for its length.
+        let written = unsafe {
+            libc::pwritev(
+                file.as_raw_fd(),
+                bufs.as_ptr().cast::<libc::iovec>(),
+                bufs.len().min(IOV_MAX) as libc::c_int,
+                offset as libc::off_t,
+            )
+        };
+        match written {
+            n if n < 0 => {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+            0 => return Err(io::ErrorKind::WriteZero.into()),
+            n => {
+                offset += n as u64;
+                IoSlice::advance_slices(&mut bufs, n as usize);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes every `(offset, bytes)` pair in offset order, with each run of
+/// adjacent pairs merged into as few calls as `IOV_MAX` allows. Returns how
+/// many calls the merging saved.
+pub(crate) fn write_coalesced(file: &File, writes: &mut [(u64, &[u8])]) -> io::Result<usize> {
+    writes.sort_unstable_by_key(|&(offset, _)| offset);
+    let mut saved = 0;
+    for run in writes.chunk_by(|a, b| a.0 + a.1.len() as u64 == b.0) {
+        let mut bufs: Vec<IoSlice<'_>> = run.iter().map(|(_, bytes)| IoSlice::new(bytes)).collect();
+        write_all_vectored_at(file, &mut bufs, run[0].0)?;
+        saved += run.len() - run.len().div_ceil(IOV_MAX);
+    }
+    Ok(saved)
+}
+
+/// Fills all of `bufs` from consecutive bytes starting at `offset`, in
+/// calls of at most `IOV_MAX` buffers, retrying after short reads.
+pub(crate) fn read_exact_vectored_at(
+    file: &File,
+    mut bufs: &mut [IoSliceMut<'_>],
+    mut offset: u64,
+) -> io::Result<()> {
+    IoSliceMut::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        // SAFETY: every slice in `bufs` is valid for writes for its length.
+        let read = unsafe {
+            libc::preadv(
+                file.as_raw_fd(),
+                bufs.as_ptr().cast::<libc::iovec>(),
+                bufs.len().min(IOV_MAX) as libc::c_int,
+                offset as libc::off_t,
+            )
+        };
+        match read {
+            n if n < 0 => {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                offset += n as u64;
+                IoSliceMut::advance_slices(&mut bufs, n as usize);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, OpenOptions};
+
+    #[test]
+    fn test_vectored_round_trip() {
+        let path = "test_vectored_io.bin";
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap();
+
+        let (first, second) = (vec![1u8; 4096], vec![2u8; 100]);
+        let mut bufs = [
+            IoSlice::new(&first),
+            IoSlice::new(&[]),
+            IoSlice::new(&second),
+        ];
+        write_all_vectored_at(&file, &mut bufs, 10).unwrap();
+
+        let (mut head, mut tail) = (vec![0u8; 4000], vec![0u8; 196]);
+        let mut bufs = [IoSliceMut::new(&mut head), IoSliceMut::new(&mut tail)];
+        read_exact_vectored_at(&file, &mut bufs, 10).unwrap();
+        assert!(head.iter().all(|&byte| byte == 1));
+        assert!(tail[..96].iter().all(|&byte| byte == 1));
+        assert!(tail[96..].iter().all(|&byte| byte == 2));
+
+        let mut past_end = [IoSliceMut::new(&mut head)];
+        let error = read_exact_vectored_at(&file, &mut past_end, 4000).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_more_buffers_than_iov_max() {
+        let path = "test_vectored_io_iov_max.bin";
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap();
+
+        let num_bufs = 2 * IOV_MAX + 5;
+        let chunks: Vec<[u8; 4]> = (0..num_bufs as u32).map(u32::to_le_bytes).collect();
+        let mut writes: Vec<(u64, &[u8])> = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| (4 * i as u64, &chunk[..]))
+            .collect();
+        // One run of adjacent writes takes three calls.
+        assert_eq!(write_coalesced(&file, &mut writes).unwrap(), num_bufs - 3);
+
+        let mut read_back = vec![[0u8; 4]; num_bufs];
+        let mut bufs: Vec<IoSliceMut<'_>> = read_back
+            .iter_mut()
+            .map(|chunk| IoSliceMut::new(chunk))
+            .collect();
+        read_exact_vectored_at(&file, &mut bufs, 0).unwrap();
+        assert_eq!(read_back, chunks);
+        let _ = fs::remove_file(path);
+    }
+}
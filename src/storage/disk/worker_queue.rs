@@ -0,0 +1,306 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, Condvar, Mutex},
+};
+
+use crate::common::{config::PageId, exception::Exception};
+use crate::storage::disk::disk_scheduler::{DiskRequest, Fence, RequestPriority, WorkerTask};
+
+const NUM_PRIORITIES: usize = RequestPriority::Background as usize + 1;
+// How many times in a row a priority may be passed over for a higher one
+// before it is served anyway.
+const STARVATION_LIMIT: usize = 8;
+
+/// The queue between `DiskScheduler::schedule` and one worker. Requests are
+/// served by priority, but never ahead of an earlier request for the same
+/// page or of a fence queued before them. Once `capacity` requests are
+/// queued, `put` blocks until the worker takes one; fences are always
+/// accepted. After `close`, `get` returns `None` once everything queued
+/// before has been served, which stops the worker.
+pub(crate) struct WorkerQueue {
+    state: Mutex<QueueState>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+}
+
+struct QueueState {
+    // Queued requests of each priority, keyed by arrival order.
+    lanes: [BTreeMap<u64, DiskRequest>; NUM_PRIORITIES],
+    // Arrival order and priority of the queued requests of each page.
+    pages: HashMap<PageId, VecDeque<(u64, usize)>>,
+    // Fences and the stop marker, which nothing queued later may overtake.
+    barriers: VecDeque<(u64, Option<Arc<Fence>>)>,
+    passed_over: [usize; NUM_PRIORITIES],
+    next_sequence: u64,
+    num_requests: usize,
+    closed: bool,
+}
+
+impl WorkerQueue {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                lanes: Default::default(),
+                pages: HashMap::new(),
+                barriers: VecDeque::new(),
+                passed_over: [0; NUM_PRIORITIES],
+                next_sequence: 0,
+                num_requests: 0,
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Queues `task`, waiting for room if it is a request and the queue is
+    /// full. Fails once the queue is closed, also while waiting.
+    pub fn put(&self, task: WorkerTask) -> Result<(), Exception> {
+        let mut state = self.state.lock()?;
+        if let WorkerTask::Request(_) = task {
+            while !state.closed && state.num_requests >= self.capacity {
+                state = self.not_full.wait(state)?;
+            }
+        }
+        if state.closed {
+            return Err(Exception::Execution("Worker queue is closed"));
+        }
+        state.push(Some(task));
+        self.not_empty.notify_all();
+        Ok(())
+    }
+
+    /// Stops accepting tasks and releases every `put` waiting for room. The
+    /// worker still serves what was queued before.
+    pub fn close(&self) -> Result<(), Exception> {
+        let mut state = self.state.lock()?;
+        if !state.closed {
+            state.closed = true;
+            state.push(None);
+        }
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+        Ok(())
+    }
+
+    /// Blocks until a task or the stop marker can be served.
+    pub fn get(&self) -> Result<Option<WorkerTask>, Exception> {
+        let mut state = self.state.lock()?;
+        loop {
+            if let Some(task) = self.pop(&mut state) {
+                return Ok(task);
+            }
+            state = self.not_empty.wait(state)?;
+        }
+    }
+
+    /// Returns the next task or stop marker if one is queued, without
+    /// blocking.
+    pub fn try_get(&self) -> Result<Option<Option<WorkerTask>>, Exception> {
+        let mut state = self.state.lock()?;
+        Ok(self.pop(&mut state))
+    }
+
+    fn pop(&self, state: &mut QueueState) -> Option<Option<WorkerTask>> {
+        let task = state.pop()?;
+        if let Some(WorkerTask::Request(_)) = task {
+            state.num_requests -= 1;
+            self.not_full.notify_one();
+        }
+        Some(task)
+    }
+}
+
+impl QueueState {
+    fn push(&mut self, task: Option<WorkerTask>) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        match task {
+            Some(WorkerTask::Request(request)) => {
+                let lane = request.priority as usize;
+                self.pages
+                    .entry(request.page_id)
+                    .or_default()
+                    .push_back((sequence, lane));
+                self.lanes[lane].insert(sequence, request);
+                self.num_requests += 1;
+            }
+            Some(WorkerTask::Fence(fence)) => self.barriers.push_back((sequence, Some(fence))),
+            None => self.barriers.push_back((sequence, None)),
+        }
+    }
+
+    fn pop(&mut self) -> Option<Option<WorkerTask>> {
+        let limit = self
+            .barriers
+            .front()
+            .map_or(u64::MAX, |&(sequence, _)| sequence);
+        let ready: Vec<bool> = self
+            .lanes
+            .iter()
+            .map(|lane| {
+                lane.first_key_value()
+                    .is_some_and(|(&sequence, _)| sequence < limit)
+            })
+            .collect();
+        let Some(highest) = ready.iter().position(|&ready| ready) else {
+            // Everything queued before the first barrier has been served.
+            let (_, fence) = self.barriers.pop_front()?;
+            return Some(fence.map(WorkerTask::Fence));
+        };
+
+        let mut lane = highest;
+        for (lower, _) in ready
+            .iter()
+            .enumerate()
+            .skip(highest + 1)
+            .filter(|(_, ready)| **ready)
+        {
+            self.passed_over[lower] += 1;
+            if lane == highest && self.passed_over[lower] > STARVATION_LIMIT {
+                lane = lower;
+            }
+        }
+        self.passed_over[lane] = 0;
+
+        // An earlier request for the same page goes first, whatever its
+        // priority.
+        let (&sequence, request) = self.lanes[lane].first_key_value()?;
+        let page_id = request.page_id;
+        let page_queue = self.pages.get_mut(&page_id)?;
+        let (sequence, lane) = match page_queue.front() {
+            Some(&(first, first_lane)) if first != sequence => (first, first_lane),
+            _ => (sequence, lane),
+        };
+        page_queue.pop_front();
+        if page_queue.is_empty() {
+            self.pages.remove(&page_id);
+        }
+        let request: DiskRequest = self.lanes[lane].remove(&sequence)?;
+        Some(Some(WorkerTask::Request(request)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::disk::{disk_scheduler::RequestType, page_buffer::PageBuffer};
+    use std::{sync::mpsc, thread, time::Duration};
+
+    fn request(page_id: PageId, priority: RequestPriority) -> WorkerTask {
+        let (tx, _) = mpsc::channel();
+        WorkerTask::Request(DiskRequest {
+            request_type: RequestType::Write,
+            data: PageBuffer::empty(),
+            page_id,
+            priority,
+            callback: tx.into(),
+        })
+    }
+
+    fn next_page(queue: &WorkerQueue) -> Option<PageId> {
+        match queue.try_get().unwrap() {
+            Some(Some(WorkerTask::Request(request))) => Some(request.page_id),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_higher_priority_first() {
+        let queue = WorkerQueue::with_capacity(usize::MAX);
+        queue.put(request(1, RequestPriority::Background)).unwrap();
+        queue.put(request(2, RequestPriority::Prefetch)).unwrap();
+        queue.put(request(3, RequestPriority::Foreground)).unwrap();
+        queue.put(request(4, RequestPriority::Foreground)).unwrap();
+        let order: Vec<PageId> = (0..4).filter_map(|_| next_page(&queue)).collect();
+        assert_eq!(order, vec![3, 4, 2, 1]);
+        assert!(queue.try_get().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_same_page_keeps_order() {
+        let queue = WorkerQueue::with_capacity(usize::MAX);
+        queue.put(request(7, RequestPriority::Background)).unwrap();
+        queue.put(request(8, RequestPriority::Background)).unwrap();
+        queue.put(request(7, RequestPriority::Foreground)).unwrap();
+        let Some(Some(WorkerTask::Request(first))) = queue.try_get().unwrap() else {
+            panic!("expected a request");
+        };
+        assert_eq!(first.page_id, 7);
+        assert_eq!(first.priority, RequestPriority::Background);
+        assert_eq!(next_page(&queue), Some(7));
+        assert_eq!(next_page(&queue), Some(8));
+    }
+
+    #[test]
+    fn test_starvation_protection() {
+        let queue = WorkerQueue::with_capacity(usize::MAX);
+        queue.put(request(0, RequestPriority::Background)).unwrap();
+        for page_id in 1..=100 {
+            queue
+                .put(request(page_id, RequestPriority::Foreground))
+                .unwrap();
+        }
+        let order: Vec<PageId> = (0..101).filter_map(|_| next_page(&queue)).collect();
+        let position = order.iter().position(|&page_id| page_id == 0).unwrap();
+        assert_eq!(position, STARVATION_LIMIT);
+    }
+
+    #[test]
+    fn test_put_blocks_at_capacity() {
+        let queue = Arc::new(WorkerQueue::with_capacity(2));
+        queue.put(request(1, RequestPriority::Foreground)).unwrap();
+        queue.put(request(2, RequestPriority::Foreground)).unwrap();
+
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.put(request(3, RequestPriority::Foreground)))
+        };
+        thread::sleep(Duration::from_millis(20));
+        assert!(!producer.is_finished());
+        assert_eq!(next_page(&queue), Some(1));
+        producer.join().unwrap().unwrap();
+        assert_eq!(next_page(&queue), Some(2));
+        assert_eq!(next_page(&queue), Some(3));
+    }
+
+    #[test]
+    fn test_close_releases_blocked_producer() {
+        let queue = Arc::new(WorkerQueue::with_capacity(1));
+        queue.put(request(1, RequestPriority::Foreground)).unwrap();
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.put(request(2, RequestPriority::Foreground)))
+        };
+        thread::sleep(Duration::from_millis(20));
+        assert!(!producer.is_finished());
+
+        queue.close().unwrap();
+        assert!(producer.join().unwrap().is_err());
+        assert!(queue.put(request(3, RequestPriority::Foreground)).is_err());
+        // What was queued before is still served, then the worker stops.
+        assert_eq!(next_page(&queue), Some(1));
+        assert!(queue.get().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_barriers_are_not_overtaken() {
+        let queue = WorkerQueue::with_capacity(usize::MAX);
+        let WorkerTask::Request(barrier) = request(0, RequestPriority::Foreground) else {
+            unreachable!();
+        };
+        queue.put(request(1, RequestPriority::Background)).unwrap();
+        queue
+            .put(WorkerTask::Fence(Arc::new(Fence::new(barrier, 1))))
+            .unwrap();
+        queue.put(request(2, RequestPriority::Foreground)).unwrap();
+        queue.close().unwrap();
+
+        assert_eq!(next_page(&queue), Some(1));
+        assert!(matches!(queue.get().unwrap(), Some(WorkerTask::Fence(_))));
+        assert_eq!(next_page(&queue), Some(2));
+        assert!(queue.get().unwrap().is_none());
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::{
+    RwLock, RwLockReadGuard, RwLockWriteGuard,
+    atomic::{AtomicI32, AtomicU64, AtomicUsize, Ordering},
+};
+
+use crate::common::{
+    config::{INVALID_PAGE_ID, PageId},
+    exception::Exception,
+};
+use crate::storage::disk::page_buffer::PageBuffer;
+
+/// A frame of the buffer pool together with the page it currently holds.
+/// The lock around the data is the page latch: hold it shared to read the
+/// page and exclusively to modify it. The page id, pin count and dirty flag
+/// are maintained by the buffer pool.
+pub struct Page {
+    data: RwLock<PageBuffer>,
+    page_id: AtomicI32,
+    pin_count: AtomicUsize,
+    // The dirty flag in the lowest bit, and above it a count of the changes
+    // to the flag, which tells a write whether the page changed after it
+    // was copied.
+    dirty_state: AtomicU64,
+}
+
+impl Page {
+    pub(crate) fn new() -> Self {
+        Self {
+            data: RwLock::new(PageBuffer::new()),
+            page_id: AtomicI32::new(INVALID_PAGE_ID),
+            pin_count: AtomicUsize::new(0),
+            dirty_state: AtomicU64::new(0),
+        }
+    }
+
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, PageBuffer>, Exception> {
+        Ok(self.data.read()?)
+    }
+
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, PageBuffer>, Exception> {
+        Ok(self.data.write()?)
+    }
+
+    pub fn get_page_id(&self) -> PageId {
+        self.page_id.load(Ordering::Acquire)
+    }
+
+    pub fn get_pin_count(&self) -> usize {
+        self.pin_count.load(Ordering::Acquire)
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.get_dirty_state() & 1 == 1
+    }
+
+    /// Makes the frame hold `page_id`, unpinned and clean.
+    pub(crate) fn reset(&self, page_id: PageId) {
+        self.page_id.store(page_id, Ordering::Release);
+        self.pin_count.store(0, Ordering::Release);
+        self.set_dirty(false);
+    }
+
+    pub(crate) fn pin(&self) {
+        self.pin_count.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Drops one pin and returns the pins left.
+    pub(crate) fn unpin(&self) -> usize {
+        self.pin_count.fetch_sub(1, Ordering::AcqRel) - 1
+    }
+
+    pub(crate) fn set_dirty(&self, is_dirty: bool) {
+        let _ = self
+            .dirty_state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                Some((state | 1) + 1 + is_dirty as u64)
+            });
+    }
+
+    /// The dirty flag together with a count of its changes. Taken when a
+    /// copy of the page is written, it lets `set_clean_since` tell whether
+    /// the page changed meanwhile.
+    pub(crate) fn get_dirty_state(&self) -> u64 {
+        self.dirty_state.load(Ordering::Acquire)
+    }
+
+    /// Marks the page clean if its dirty state is still `dirty_state`, i.e.
+    /// if it was neither marked dirty nor reset since.
+    pub(crate) fn set_clean_since(&self, dirty_state: u64) {
+        if dirty_state & 1 == 1 {
+            let _ = self.dirty_state.compare_exchange(
+                dirty_state,
+                dirty_state + 1,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
+        }
+    }
+}